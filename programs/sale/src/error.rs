@@ -0,0 +1,141 @@
+use anchor_lang::prelude::*;
+
+#[error_code]
+pub enum SaleError {
+    #[msg("The sale has not started yet")]
+    SaleNotStarted,
+    #[msg("The sale has ended or sold out")]
+    SaleEnded,
+    // No longer returned now that the last purchase is partially filled; kept
+    // so the codes of the variants below stay stable
+    #[msg("Purchase exceeds the tokens left in the sale")]
+    Overpayment,
+    #[msg("Signer is not the campaign admin")]
+    Unauthorized,
+    #[msg("Campaign balance above rent is too low for this withdrawal")]
+    InsufficientVaultBalance,
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+    #[msg("Token price must be greater than zero")]
+    InvalidPrice,
+    #[msg("Total tokens for sale must be greater than zero")]
+    InvalidTokenSupply,
+    #[msg("Sale start must be before sale end")]
+    InvalidSaleWindow,
+    #[msg("Target amount exceeds what selling every token would raise")]
+    TargetExceedsSupply,
+    #[msg("Hard cap must be between the target amount and the full sale value")]
+    InvalidHardCap,
+    #[msg("Token decimals do not match the sale mint")]
+    MintDecimalsMismatch,
+    #[msg("Token vault holds fewer tokens than the sale offers")]
+    VaultUnderfunded,
+    #[msg("The sale is not open for purchases")]
+    SaleNotOpen,
+    #[msg("The sale is still running")]
+    SaleStillActive,
+    #[msg("Claiming has not been enabled for this campaign")]
+    ClaimNotEnabled,
+    #[msg("No tokens left to claim")]
+    NothingToClaim,
+    #[msg("Vesting schedule is invalid")]
+    InvalidVestingConfig,
+    #[msg("The campaign has already been finalized")]
+    AlreadyFinalized,
+    #[msg("The sale did not reach its target")]
+    SaleNotSucceeded,
+    #[msg("Refunds are only available for failed sales")]
+    SaleNotFailed,
+    #[msg("Nothing left to refund for this buyer")]
+    NothingToRefund,
+    #[msg("Sale extension must move the end forward and stay within the limit")]
+    InvalidExtension,
+    #[msg("This sale is whitelisted; use donate_whitelisted with a proof")]
+    WhitelistRequired,
+    #[msg("Merkle proof does not match the campaign whitelist")]
+    NotWhitelisted,
+    #[msg("Purchase exceeds the buyer's whitelist allocation")]
+    AllocationExceeded,
+    #[msg("Contribution is below the campaign minimum")]
+    BelowMinContribution,
+    #[msg("Contribution exceeds the per-wallet maximum")]
+    AboveMaxContribution,
+    #[msg("Minimum contribution must not exceed the per-wallet maximum or hard cap")]
+    InvalidContributionLimits,
+    #[msg("Rounds must be ordered, inside the sale window and cover every token")]
+    InvalidRounds,
+    #[msg("No sale round is open right now")]
+    NoActiveRound,
+    #[msg("The current round has sold out")]
+    RoundSoldOut,
+    #[msg("Dutch auctions need a floor below the start price and no rounds")]
+    InvalidPricingMode,
+    #[msg("This instruction does not match the campaign's payment currency")]
+    WrongPaymentMethod,
+    #[msg("Payment mint must differ from the sale mint and support the pricing mode")]
+    InvalidPaymentMint,
+    #[msg("The sale has already opened")]
+    SaleAlreadyOpened,
+    #[msg("Payment is too small to buy a single token base unit")]
+    PurchaseTooSmall,
+    #[msg("The campaign cannot move to that status from its current one")]
+    InvalidStatusTransition,
+    #[msg("New admin must be a real key different from the current admin")]
+    InvalidAdmin,
+    #[msg("No admin transfer is pending")]
+    NoPendingAdmin,
+    #[msg("This action is paused on the campaign")]
+    ActionPaused,
+    #[msg("A pause must block at least one action")]
+    InvalidPauseActions,
+    #[msg("Pause reason is too long")]
+    PauseReasonTooLong,
+    #[msg("The campaign is not paused")]
+    NotPaused,
+    #[msg("Signer set must hold distinct keys and a threshold within its size")]
+    InvalidSignerSet,
+    #[msg("The withdrawal signer set can no longer be changed")]
+    SignerSetLocked,
+    #[msg("This campaign withdraws through signer-approved proposals")]
+    MultisigRequired,
+    #[msg("Withdrawal proposal needs a positive amount and a future expiry")]
+    InvalidWithdrawalProposal,
+    #[msg("Signer has already approved this proposal")]
+    AlreadyApproved,
+    #[msg("Withdrawal proposal has already been executed")]
+    ProposalExecuted,
+    #[msg("Withdrawal proposal has expired")]
+    ProposalExpired,
+    #[msg("Withdrawal proposal does not have enough approvals")]
+    ThresholdNotMet,
+    #[msg("Protocol fee exceeds the maximum")]
+    InvalidProtocolFee,
+    #[msg("Treasury must be a real key")]
+    InvalidTreasury,
+    #[msg("Referral commission exceeds the maximum or has a budget it cannot use")]
+    InvalidReferralConfig,
+    #[msg("Buyers cannot refer themselves and the admin cannot be a referrer")]
+    InvalidReferrer,
+    #[msg("Reward accounts are missing or do not match the campaign")]
+    InvalidRewardAccounts,
+    #[msg("The buyer still has tokens to claim or a refund to take")]
+    PositionNotSettled,
+    #[msg("The campaign still holds funds, tokens or open positions")]
+    ObligationsOutstanding,
+    #[msg("The campaign's payment vault must be passed")]
+    PaymentVaultRequired,
+    #[msg("Price feed is not a price account of the configured oracle")]
+    InvalidPriceFeed,
+    #[msg("Price feed is stale or not trading")]
+    StalePrice,
+    #[msg("Price feed confidence interval is too wide")]
+    PriceTooUncertain,
+    #[msg("USD pricing needs a positive price, age and confidence bound on a fixed-price lamport sale")]
+    InvalidUsdPricing,
+    #[msg("The sale has reached its hard cap")]
+    HardCapReached,
+    #[msg("A Dutch auction's price schedule cannot be extended")]
+    AuctionNotExtendable,
+    #[msg("Fee recipient must be a real key")]
+    InvalidFeeRecipient,
+}
@@ -0,0 +1,322 @@
+use anchor_lang::prelude::*;
+
+use crate::{CampaignStatus, PauseFlags, PaymentTerms, ReferralConfig, UsdPricing};
+
+#[event]
+pub struct CampaignCreated {
+    pub campaign: Pubkey,
+    pub index: u64,
+    pub admin: Pubkey,
+    pub token_mint: Pubkey,
+    pub total_tokens: u64,
+    pub token_price: u64,
+    pub target_amount: u64,
+    pub hard_cap: u64,
+    pub protocol_fee_bps: u16,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CampaignStatusChanged {
+    pub campaign: Pubkey,
+    pub previous: CampaignStatus,
+    pub status: CampaignStatus,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AdminProposed {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AdminProposalCancelled {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AdminTransferred {
+    pub campaign: Pubkey,
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianUpdated {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub guardian: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CampaignPaused {
+    pub campaign: Pubkey,
+    pub authority: Pubkey, // Admin or guardian
+    pub actions: PauseFlags,
+    pub reason: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CampaignUnpaused {
+    pub campaign: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensDeposited {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub amount: u64,
+    pub vault_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SaleOpened {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SaleExtended {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub old_end_ts: i64,
+    pub new_end_ts: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WhitelistRootUpdated {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub whitelist_root: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SplPaymentConfigured {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub payment_mint: Pubkey,
+    pub payment_vault: Pubkey,
+    pub terms: PaymentTerms,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensPurchased {
+    pub campaign: Pubkey,
+    pub buyer: Pubkey,
+    pub referrer: Pubkey, // Default pubkey when the purchase was not referred
+    pub amount_paid: u64,
+    pub tokens_bought: u64,
+    pub token_price: u64,
+    pub buyer_contributed: u64, // Buyer's running total
+    pub buyer_tokens: u64,      // Buyer's running total
+    pub tokens_sold: u64,       // Campaign running total
+    pub amount_donated: u64,    // Campaign running total
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SaleFinalized {
+    pub campaign: Pubkey,
+    pub status: CampaignStatus, // Succeeded or Failed
+    pub amount_donated: u64,
+    pub tokens_sold: u64,
+    pub clearing_price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralsConfigured {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub referral: ReferralConfig,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferrerRegistered {
+    pub campaign: Pubkey,
+    pub referrer: Pubkey,
+    pub referrer_stats: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralCredited {
+    pub campaign: Pubkey,
+    pub referrer: Pubkey,
+    pub buyer: Pubkey,
+    pub volume: u64, // Payment units charged to the buyer
+    pub reward: u64, // Payment units or bonus tokens, per the campaign's config
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralRewardsClaimed {
+    pub campaign: Pubkey,
+    pub referrer: Pubkey,
+    pub amount: u64,
+    pub total_claimed: u64, // Referrer's running total
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FundsWithdrawn {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,       // Gross, including the fee
+    pub protocol_fee: u64, // Part of `amount` sent to the protocol
+    pub total_withdrawn: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalSignersUpdated {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalProposed {
+    pub campaign: Pubkey,
+    pub proposal: Pubkey,
+    pub index: u64,
+    pub proposer: Pubkey,
+    pub amount: u64,
+    pub recipient: Pubkey,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalApproved {
+    pub campaign: Pubkey,
+    pub proposal: Pubkey,
+    pub signer: Pubkey,
+    pub approvals: u8,
+    pub threshold: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalExecuted {
+    pub campaign: Pubkey,
+    pub proposal: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub protocol_fee: u64,
+    pub total_withdrawn: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TreasuryUpdated {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProtocolConfigUpdated {
+    pub admin: Pubkey,
+    pub fee_bps: u16,
+    pub fee_recipient: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ClaimEnabled {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub tge_ts: i64,
+}
+
+#[event]
+pub struct TokensClaimed {
+    pub campaign: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub rebate: u64, // Lamports returned under uniform clearing
+    pub buyer_claimed: u64,
+    pub total_claimed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UnsoldTokensReclaimed {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionClosed {
+    pub campaign: Pubkey,
+    pub buyer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CampaignClosed {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Refunded {
+    pub campaign: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub total_refunded: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OracleProgramUpdated {
+    pub admin: Pubkey,
+    pub oracle_program: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UsdPricingConfigured {
+    pub campaign: Pubkey,
+    pub admin: Pubkey,
+    pub usd_pricing: UsdPricing,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PriceQuoted {
+    pub campaign: Pubkey,
+    pub usd_price: u64,
+    pub sol_price: i64,
+    pub sol_expo: i32,
+    pub publish_time: i64,
+    pub token_price: u64,
+    pub timestamp: i64,
+}
@@ -1,203 +1,2154 @@
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, TransferChecked};
+
+mod error;
+mod events;
+mod merkle;
+mod multisig;
+mod oracle;
+mod pause;
+mod payout;
+mod pricing;
+mod purchase;
+mod referral;
+mod rounds;
+mod status;
+mod vesting;
+
+pub use error::SaleError;
+pub use events::*;
+pub use multisig::MAX_SIGNERS;
+pub use oracle::UsdPricing;
+pub use pause::{PauseFlags, MAX_PAUSE_REASON};
+pub use pricing::PricingMode;
+pub use referral::{ReferralConfig, ReferralReward, MAX_REFERRAL_BPS};
+pub use rounds::{RoundConfig, SaleRound, UnsoldPolicy, MAX_ROUNDS};
+pub use status::CampaignStatus;
+pub use vesting::VestingConfig;
 
 declare_id!("CkvvUYGVEtRoD6Ky2Gs7NthwK3jhrKFkkoxJiKxKNmgU");
 
-#[program]
-pub mod smart_contracts {
-    use super::*;
+// Upper bound on how far `extend_sale` may push a campaign's end, in total
+#[constant]
+pub const MAX_SALE_EXTENSION: i64 = 30 * 24 * 60 * 60;
+
+// Highest protocol fee the protocol admin can set, in basis points
+#[constant]
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1_000;
+
+#[program]
+pub mod smart_contracts {
+    use super::*;
+
+    // Creates the caller's campaign registry; needed once before their first
+    // campaign
+    pub fn init_registry(ctx: Context<InitRegistry>) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
+        registry.admin = ctx.accounts.user.key();
+        registry.next_index = 0;
+        registry.bump = ctx.bumps.registry;
+        Ok(())
+    }
+
+    // Creates a campaign at the caller's next campaign index
+    pub fn create(ctx: Context<Create>, args: CreateCampaignArgs) -> Result<()> {
+        args.validate()?;
+        require!(
+            args.token_decimals == ctx.accounts.token_mint.decimals,
+            SaleError::MintDecimalsMismatch
+        );
+
+        let campaign = &mut ctx.accounts.campaign;
+
+        campaign.admin = *ctx.accounts.user.key;
+        campaign.creator = *ctx.accounts.user.key;
+        campaign.index = ctx.accounts.registry.next_index;
+        campaign.treasury = *ctx.accounts.user.key;
+        // The fee is fixed when the campaign is created
+        campaign.protocol_fee_bps = ctx.accounts.protocol_config.fee_bps;
+        campaign.token_mint = ctx.accounts.token_mint.key();
+        campaign.token_vault = ctx.accounts.token_vault.key();
+        campaign.bump = ctx.bumps.campaign;
+        // Amounts are in lamports until configure_spl_payment restates them
+        campaign.target_amount = args.target_amount;
+        campaign.hard_cap = args.hard_cap;
+        campaign.amount_donated = 0;
+        campaign.amount_withdrawn = 0;
+        campaign.total_tokens = args.total_tokens;
+        campaign.token_price = args.token_price;
+        campaign.token_decimals = args.token_decimals;
+        campaign.start_ts = args.start_ts;
+        campaign.end_ts = args.end_ts;
+        campaign.vesting = args.vesting;
+        campaign.whitelist_root = args.whitelist_root;
+        campaign.min_contribution = args.min_contribution;
+        campaign.max_contribution_per_wallet = args.max_contribution_per_wallet;
+        campaign.rounds = args
+            .rounds
+            .iter()
+            .map(|config| SaleRound {
+                config: *config,
+                tokens_sold: 0,
+            })
+            .collect();
+        campaign.unsold_policy = args.unsold_policy;
+        campaign.pricing_mode = args.pricing_mode;
+        campaign.tokens_sold = 0;
+        campaign.status = CampaignStatus::Draft; // Opens once the vault is funded
+
+        let registry = &mut ctx.accounts.registry;
+        registry.campaigns.push(campaign.key());
+        registry.next_index = registry
+            .next_index
+            .checked_add(1)
+            .ok_or(SaleError::MathOverflow)?;
+
+        emit!(CampaignCreated {
+            campaign: campaign.key(),
+            index: campaign.index,
+            admin: campaign.admin,
+            token_mint: campaign.token_mint,
+            total_tokens: campaign.total_tokens,
+            token_price: campaign.token_price,
+            target_amount: campaign.target_amount,
+            hard_cap: campaign.hard_cap,
+            protocol_fee_bps: campaign.protocol_fee_bps,
+            start_ts: campaign.start_ts,
+            end_ts: campaign.end_ts,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Moves sale tokens from the admin into the campaign vault
+    pub fn deposit_tokens(ctx: Context<DepositTokens>, amount: u64) -> Result<()> {
+        require!(
+            !ctx.accounts.campaign.status.is_settled(),
+            SaleError::AlreadyFinalized
+        );
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.admin_token_account.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.token_vault.to_account_info(),
+            authority: ctx.accounts.admin.to_account_info(),
+        };
+        token::transfer_checked(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts),
+            amount,
+            ctx.accounts.token_mint.decimals,
+        )?;
+
+        ctx.accounts.token_vault.reload()?;
+        emit!(TokensDeposited {
+            campaign: ctx.accounts.campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            amount,
+            vault_balance: ctx.accounts.token_vault.amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Opens the sale once the vault can cover every token for sale. The
+    // campaign is Scheduled until start_ts, Active after.
+    pub fn open_sale(ctx: Context<OpenSale>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(
+            campaign.status == CampaignStatus::Draft,
+            SaleError::SaleAlreadyOpened
+        );
+        // Bonus tokens for referrers come on top of the sale supply
+        let required = campaign
+            .total_tokens
+            .checked_add(campaign.referral.token_budget)
+            .ok_or(SaleError::MathOverflow)?;
+        require!(
+            ctx.accounts.token_vault.amount >= required,
+            SaleError::VaultUnderfunded
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(now < campaign.end_ts, SaleError::SaleEnded);
+        let next = if now < campaign.start_ts {
+            CampaignStatus::Scheduled
+        } else {
+            CampaignStatus::Active
+        };
+        status::transition(campaign, next, now)?;
+
+        emit!(SaleOpened {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Moves a Scheduled campaign to Active once start_ts has passed; callable
+    // by anyone. Purchases do the same on their own.
+    pub fn activate_sale(ctx: Context<ActivateSale>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        // Draft campaigns must be opened, which checks the vault, first
+        require!(
+            campaign.status == CampaignStatus::Scheduled,
+            SaleError::SaleNotOpen
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= campaign.start_ts, SaleError::SaleNotStarted);
+        status::transition(campaign, CampaignStatus::Active, now)
+    }
+
+    // Calls off a campaign that has not been finalized; buyers can then refund
+    pub fn cancel_campaign(ctx: Context<CancelCampaign>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(!campaign.status.is_settled(), SaleError::AlreadyFinalized);
+        status::transition(
+            campaign,
+            CampaignStatus::Cancelled,
+            Clock::get()?.unix_timestamp,
+        )
+    }
+
+    // Appoints the key that may pause and unpause alongside the admin; the
+    // default pubkey removes the guardian
+    pub fn set_guardian(ctx: Context<SetGuardian>, guardian: Pubkey) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.guardian = guardian;
+
+        emit!(GuardianUpdated {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            guardian,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Blocks the selected actions until unpause; calling it again while paused
+    // replaces the flags and reason
+    pub fn pause(ctx: Context<SetPause>, actions: PauseFlags, reason: String) -> Result<()> {
+        require!(actions.any(), SaleError::InvalidPauseActions);
+        require!(
+            reason.len() <= MAX_PAUSE_REASON,
+            SaleError::PauseReasonTooLong
+        );
+        let campaign = &mut ctx.accounts.campaign;
+        let now = Clock::get()?.unix_timestamp;
+        campaign.paused = actions;
+        campaign.pause_reason = reason.clone();
+        campaign.paused_at = now;
+
+        emit!(CampaignPaused {
+            campaign: campaign.key(),
+            authority: ctx.accounts.authority.key(),
+            actions,
+            reason,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Lifts every pause on the campaign
+    pub fn unpause(ctx: Context<SetPause>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(campaign.paused.any(), SaleError::NotPaused);
+        campaign.paused = PauseFlags::default();
+        campaign.pause_reason.clear();
+        campaign.paused_at = 0;
+
+        emit!(CampaignUnpaused {
+            campaign: campaign.key(),
+            authority: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Nominates a new admin; the transfer completes when they call accept_admin
+    pub fn propose_admin(ctx: Context<ProposeAdmin>, new_admin: Pubkey) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(
+            new_admin != Pubkey::default() && new_admin != campaign.admin,
+            SaleError::InvalidAdmin
+        );
+        campaign.pending_admin = new_admin;
+
+        emit!(AdminProposed {
+            campaign: campaign.key(),
+            admin: campaign.admin,
+            pending_admin: new_admin,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Withdraws an admin proposal that has not been accepted yet
+    pub fn cancel_admin_proposal(ctx: Context<ProposeAdmin>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(
+            campaign.pending_admin != Pubkey::default(),
+            SaleError::NoPendingAdmin
+        );
+        let pending_admin = campaign.pending_admin;
+        campaign.pending_admin = Pubkey::default();
+
+        emit!(AdminProposalCancelled {
+            campaign: campaign.key(),
+            admin: campaign.admin,
+            pending_admin,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Completes an admin transfer; must be signed by the proposed admin
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(
+            campaign.pending_admin != Pubkey::default(),
+            SaleError::NoPendingAdmin
+        );
+        require_keys_eq!(
+            campaign.pending_admin,
+            ctx.accounts.new_admin.key(),
+            SaleError::Unauthorized
+        );
+        let previous_admin = campaign.admin;
+        campaign.admin = campaign.pending_admin;
+        campaign.pending_admin = Pubkey::default();
+
+        emit!(AdminTransferred {
+            campaign: campaign.key(),
+            previous_admin,
+            new_admin: campaign.admin,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Pushes back the end of a running sale, up to MAX_SALE_EXTENSION overall
+    pub fn extend_sale(ctx: Context<ExtendSale>, new_end_ts: i64) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(!campaign.status.is_settled(), SaleError::AlreadyFinalized);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now < campaign.end_ts, SaleError::SaleEnded);
+        // The auction price decays over the sale window, so moving its end
+        // would reprice every later purchase
+        require!(
+            !matches!(campaign.pricing_mode, PricingMode::DutchAuction { .. }),
+            SaleError::AuctionNotExtendable
+        );
+
+        let extension = new_end_ts
+            .checked_sub(campaign.end_ts)
+            .ok_or(SaleError::MathOverflow)?;
+        let total_extension = campaign
+            .total_extension
+            .checked_add(extension)
+            .ok_or(SaleError::MathOverflow)?;
+        require!(
+            extension > 0 && total_extension <= MAX_SALE_EXTENSION,
+            SaleError::InvalidExtension
+        );
+
+        // A final round that ran to the old end keeps running to the new one
+        let old_end_ts = campaign.end_ts;
+        if let Some(last) = campaign.rounds.last_mut() {
+            if last.config.end_ts == old_end_ts {
+                last.config.end_ts = new_end_ts;
+            }
+        }
+        campaign.end_ts = new_end_ts;
+        campaign.total_extension = total_extension;
+
+        emit!(SaleExtended {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            old_end_ts,
+            new_end_ts,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Settles the sale against its target once it has ended; callable by anyone
+    pub fn finalize(ctx: Context<Finalize>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(!campaign.status.is_settled(), SaleError::AlreadyFinalized);
+        require!(
+            campaign.status != CampaignStatus::Draft,
+            SaleError::SaleNotOpen
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let sold_out = campaign.status == CampaignStatus::SoldOut;
+        require!(
+            now >= campaign.end_ts || sold_out,
+            SaleError::SaleStillActive
+        );
+        // A uniform-clearing auction keeps only what the tokens cost at the
+        // clearing price, so that is what has to meet the target
+        let mut raised = campaign.amount_donated;
+        if let PricingMode::DutchAuction {
+            floor_price,
+            uniform_clearing: true,
+        } = campaign.pricing_mode
+        {
+            // A sell-out (of the supply or the hard cap) clears at the last
+            // price paid, otherwise at the floor
+            campaign.clearing_price = if sold_out {
+                campaign.last_price
+            } else {
+                floor_price
+            };
+            raised = pricing::payment_for_tokens(
+                campaign.tokens_sold,
+                campaign.clearing_price,
+                campaign.token_decimals,
+            )?
+            .min(campaign.amount_donated);
+        }
+        let outcome = if raised >= campaign.target_amount {
+            CampaignStatus::Succeeded
+        } else {
+            CampaignStatus::Failed
+        };
+        status::transition(campaign, outcome, now)?;
+        if campaign.status == CampaignStatus::Succeeded {
+            // Whatever buyers paid above the clearing price goes back to them
+            campaign.rebates_owed = campaign.amount_donated - raised;
+        }
+
+        emit!(SaleFinalized {
+            campaign: campaign.key(),
+            status: campaign.status,
+            amount_donated: campaign.amount_donated,
+            tokens_sold: campaign.tokens_sold,
+            clearing_price: campaign.clearing_price,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Withdraw raised lamports to the campaign treasury, less the protocol fee
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        let user = &mut ctx.accounts.user;
+        require!(!campaign.paused.withdrawals, SaleError::ActionPaused);
+        // Restricts Withdrawal to campaign admin
+        require_keys_eq!(campaign.admin, *user.key, SaleError::Unauthorized);
+        require!(
+            campaign.payment_mint == Pubkey::default(),
+            SaleError::WrongPaymentMethod
+        );
+        require!(campaign.signers.is_empty(), SaleError::MultisigRequired);
+        // Raised funds stay locked for refunds unless the target was met
+        require!(campaign.status.is_successful(), SaleError::SaleNotSucceeded);
+        let available = payout::withdrawable_lamports(campaign)?;
+        require!(available >= amount, SaleError::InsufficientVaultBalance);
+
+        let (fee, net) = payout::split_fee(amount, campaign.protocol_fee_bps)?;
+        let campaign_info = campaign.to_account_info();
+        payout::send_lamports(&campaign_info, &ctx.accounts.treasury, net)?;
+        payout::send_lamports(&campaign_info, &ctx.accounts.fee_recipient, fee)?;
+        campaign.record_withdrawal(amount, fee)?;
+
+        emit!(FundsWithdrawn {
+            campaign: campaign.key(),
+            admin: user.key(),
+            treasury: campaign.treasury,
+            amount,
+            protocol_fee: fee,
+            total_withdrawn: campaign.amount_withdrawn,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Withdraw raised payment-mint funds from a successful campaign to its
+    // treasury, less the protocol fee
+    pub fn withdraw_spl(ctx: Context<WithdrawSpl>, amount: u64) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        require!(!campaign.paused.withdrawals, SaleError::ActionPaused);
+        require!(campaign.signers.is_empty(), SaleError::MultisigRequired);
+        require!(campaign.status.is_successful(), SaleError::SaleNotSucceeded);
+        require!(
+            payout::withdrawable_spl(campaign, ctx.accounts.payment_vault.amount) >= amount,
+            SaleError::InsufficientVaultBalance
+        );
+
+        let (fee, net) = payout::split_fee(amount, campaign.protocol_fee_bps)?;
+        payout::send_from_vault(
+            campaign,
+            &ctx.accounts.payment_vault,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.treasury_payment_account,
+            &ctx.accounts.token_program,
+            net,
+        )?;
+        payout::send_from_vault(
+            campaign,
+            &ctx.accounts.payment_vault,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.fee_payment_account,
+            &ctx.accounts.token_program,
+            fee,
+        )?;
+
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.record_withdrawal(amount, fee)?;
+
+        emit!(FundsWithdrawn {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            treasury: campaign.treasury,
+            amount,
+            protocol_fee: fee,
+            total_withdrawn: campaign.amount_withdrawn,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Points withdrawals at a new project treasury
+    pub fn set_treasury(ctx: Context<SetTreasury>, treasury: Pubkey) -> Result<()> {
+        require!(treasury != Pubkey::default(), SaleError::InvalidTreasury);
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.treasury = treasury;
+
+        emit!(TreasuryUpdated {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            treasury,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Creates the global protocol fee settings; only the program's upgrade
+    // authority may do this, and it becomes the protocol admin
+    pub fn init_protocol_config(
+        ctx: Context<InitProtocolConfig>,
+        fee_bps: u16,
+        fee_recipient: Pubkey,
+    ) -> Result<()> {
+        require!(
+            fee_bps <= MAX_PROTOCOL_FEE_BPS,
+            SaleError::InvalidProtocolFee
+        );
+        require!(
+            fee_recipient != Pubkey::default(),
+            SaleError::InvalidFeeRecipient
+        );
+        let config = &mut ctx.accounts.protocol_config;
+        config.admin = ctx.accounts.admin.key();
+        config.fee_bps = fee_bps;
+        config.fee_recipient = fee_recipient;
+        config.bump = ctx.bumps.protocol_config;
+
+        emit!(ProtocolConfigUpdated {
+            admin: config.admin,
+            fee_bps,
+            fee_recipient,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Changes the protocol fee for campaigns created from now on, the fee
+    // recipient, or hands the protocol to a new admin
+    pub fn update_protocol_config(
+        ctx: Context<UpdateProtocolConfig>,
+        fee_bps: u16,
+        fee_recipient: Pubkey,
+        admin: Pubkey,
+    ) -> Result<()> {
+        require!(
+            fee_bps <= MAX_PROTOCOL_FEE_BPS,
+            SaleError::InvalidProtocolFee
+        );
+        require!(
+            fee_recipient != Pubkey::default(),
+            SaleError::InvalidFeeRecipient
+        );
+        require!(admin != Pubkey::default(), SaleError::InvalidAdmin);
+        let config = &mut ctx.accounts.protocol_config;
+        config.admin = admin;
+        config.fee_bps = fee_bps;
+        config.fee_recipient = fee_recipient;
+
+        emit!(ProtocolConfigUpdated {
+            admin,
+            fee_bps,
+            fee_recipient,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Sets the program that must own price feeds used for USD pricing.
+    // Campaigns already priced in USD keep the owner they were configured with.
+    pub fn set_oracle_program(
+        ctx: Context<UpdateProtocolConfig>,
+        oracle_program: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        config.oracle_program = oracle_program;
+
+        emit!(OracleProgramUpdated {
+            admin: config.admin,
+            oracle_program,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Puts withdrawals behind `threshold` approvals from `signers`. Can be
+    // changed freely while the campaign is a draft; after that a signer set can
+    // only be added, never removed or replaced.
+    pub fn set_withdrawal_signers(
+        ctx: Context<SetWithdrawalSigners>,
+        signers: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(
+            campaign.status == CampaignStatus::Draft || campaign.signers.is_empty(),
+            SaleError::SignerSetLocked
+        );
+        multisig::validate_signers(&signers, threshold)?;
+        campaign.signers = signers;
+        campaign.threshold = threshold;
+
+        emit!(WithdrawalSignersUpdated {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            signers: campaign.signers.clone(),
+            threshold,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Opens a withdrawal proposal; the proposer's approval is counted
+    pub fn propose_withdrawal(
+        ctx: Context<ProposeWithdrawal>,
+        amount: u64,
+        recipient: Pubkey,
+        expires_at: i64,
+    ) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        let proposer = ctx.accounts.proposer.key();
+        require!(
+            campaign.signers.contains(&proposer),
+            SaleError::Unauthorized
+        );
+        require!(amount > 0, SaleError::InvalidWithdrawalProposal);
+        let now = Clock::get()?.unix_timestamp;
+        require!(expires_at > now, SaleError::InvalidWithdrawalProposal);
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.campaign = campaign.key();
+        proposal.index = campaign.proposal_count;
+        proposal.proposer = proposer;
+        proposal.amount = amount;
+        proposal.recipient = recipient;
+        proposal.expires_at = expires_at;
+        proposal.approvals = vec![proposer];
+        proposal.executed = false;
+        proposal.bump = ctx.bumps.proposal;
+        campaign.proposal_count = campaign
+            .proposal_count
+            .checked_add(1)
+            .ok_or(SaleError::MathOverflow)?;
+
+        emit!(WithdrawalProposed {
+            campaign: campaign.key(),
+            proposal: proposal.key(),
+            index: proposal.index,
+            proposer,
+            amount,
+            recipient,
+            expires_at,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Adds the caller's approval to a live withdrawal proposal
+    pub fn approve_withdrawal(ctx: Context<ApproveWithdrawal>) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        let proposal = &mut ctx.accounts.proposal;
+        let signer = ctx.accounts.signer.key();
+        require!(campaign.signers.contains(&signer), SaleError::Unauthorized);
+        let now = Clock::get()?.unix_timestamp;
+        proposal.check_live(now)?;
+        require!(
+            !proposal.approvals.contains(&signer),
+            SaleError::AlreadyApproved
+        );
+        // Approvals from keys no longer in the signer set are dropped, which
+        // also keeps the list within MAX_SIGNERS
+        proposal
+            .approvals
+            .retain(|approver| campaign.signers.contains(approver));
+        proposal.approvals.push(signer);
+
+        emit!(WithdrawalApproved {
+            campaign: campaign.key(),
+            proposal: proposal.key(),
+            signer,
+            approvals: proposal.approvals.len() as u8,
+            threshold: campaign.threshold,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Pays out an approved lamport withdrawal proposal, less the protocol fee;
+    // callable by anyone
+    pub fn execute_withdrawal(ctx: Context<ExecuteWithdrawal>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!campaign.paused.withdrawals, SaleError::ActionPaused);
+        require!(
+            campaign.payment_mint == Pubkey::default(),
+            SaleError::WrongPaymentMethod
+        );
+        require!(campaign.status.is_successful(), SaleError::SaleNotSucceeded);
+        let now = Clock::get()?.unix_timestamp;
+        proposal.check_approved(now, &campaign.signers, campaign.threshold)?;
+
+        let amount = proposal.amount;
+        let available = payout::withdrawable_lamports(campaign)?;
+        require!(available >= amount, SaleError::InsufficientVaultBalance);
+        proposal.executed = true;
+
+        let (fee, net) = payout::split_fee(amount, campaign.protocol_fee_bps)?;
+        let campaign_info = campaign.to_account_info();
+        payout::send_lamports(&campaign_info, &ctx.accounts.recipient, net)?;
+        payout::send_lamports(&campaign_info, &ctx.accounts.fee_recipient, fee)?;
+        campaign.record_withdrawal(amount, fee)?;
+
+        emit!(WithdrawalExecuted {
+            campaign: campaign.key(),
+            proposal: proposal.key(),
+            recipient: proposal.recipient,
+            amount,
+            protocol_fee: fee,
+            total_withdrawn: campaign.amount_withdrawn,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Pays out an approved payment-mint withdrawal proposal, less the protocol
+    // fee; callable by anyone
+    pub fn execute_withdrawal_spl(ctx: Context<ExecuteWithdrawalSpl>) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!campaign.paused.withdrawals, SaleError::ActionPaused);
+        require!(campaign.status.is_successful(), SaleError::SaleNotSucceeded);
+        let now = Clock::get()?.unix_timestamp;
+        proposal.check_approved(now, &campaign.signers, campaign.threshold)?;
+
+        let amount = proposal.amount;
+        require!(
+            payout::withdrawable_spl(campaign, ctx.accounts.payment_vault.amount) >= amount,
+            SaleError::InsufficientVaultBalance
+        );
+        proposal.executed = true;
+
+        let (fee, net) = payout::split_fee(amount, campaign.protocol_fee_bps)?;
+        payout::send_from_vault(
+            campaign,
+            &ctx.accounts.payment_vault,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.recipient_payment_account,
+            &ctx.accounts.token_program,
+            net,
+        )?;
+        payout::send_from_vault(
+            campaign,
+            &ctx.accounts.payment_vault,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.fee_payment_account,
+            &ctx.accounts.token_program,
+            fee,
+        )?;
+
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.record_withdrawal(amount, fee)?;
+
+        emit!(WithdrawalExecuted {
+            campaign: campaign.key(),
+            proposal: proposal.key(),
+            recipient: proposal.recipient,
+            amount,
+            protocol_fee: fee,
+            total_withdrawn: campaign.amount_withdrawn,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Sets the referral commission; fixed once the sale opens
+    pub fn configure_referrals(
+        ctx: Context<ConfigureReferrals>,
+        referral: ReferralConfig,
+    ) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(
+            campaign.status == CampaignStatus::Draft,
+            SaleError::SaleAlreadyOpened
+        );
+        referral.validate()?;
+        // A uniform-clearing auction rebates part of each payment after it
+        // closes, so a commission on the amount charged would overpay
+        require!(
+            referral.reward != ReferralReward::Payment
+                || referral.commission_bps == 0
+                || !matches!(
+                    campaign.pricing_mode,
+                    PricingMode::DutchAuction {
+                        uniform_clearing: true,
+                        ..
+                    }
+                ),
+            SaleError::InvalidReferralConfig
+        );
+        campaign.referral = referral;
+
+        emit!(ReferralsConfigured {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            referral,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Creates the caller's referrer stats for a campaign; buyers pass this
+    // account to the donate instructions to credit the referrer
+    pub fn register_referrer(ctx: Context<RegisterReferrer>) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        let referrer = ctx.accounts.referrer.key();
+        require_keys_neq!(referrer, campaign.admin, SaleError::InvalidReferrer);
+
+        let stats = &mut ctx.accounts.referrer_stats;
+        stats.campaign = campaign.key();
+        stats.referrer = referrer;
+        stats.bump = ctx.bumps.referrer_stats;
+
+        emit!(ReferrerRegistered {
+            campaign: campaign.key(),
+            referrer,
+            referrer_stats: stats.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Pays out a referrer's unclaimed commission once the sale has succeeded.
+    // Lamport commissions need no token accounts; payment-mint commissions
+    // and bonus tokens need the paying vault, its mint and the referrer's
+    // token account for that mint.
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        let stats = &mut ctx.accounts.referrer_stats;
+        require!(!campaign.paused.claims, SaleError::ActionPaused);
+        require!(campaign.status.is_successful(), SaleError::SaleNotSucceeded);
+        let amount = stats
+            .rewards_earned
+            .checked_sub(stats.rewards_claimed)
+            .ok_or(SaleError::MathOverflow)?;
+        require!(amount > 0, SaleError::NothingToClaim);
+        stats.rewards_claimed = stats.rewards_earned;
+
+        let vault = match campaign.referral.reward {
+            ReferralReward::Payment => {
+                campaign.referral_payment_owed = campaign
+                    .referral_payment_owed
+                    .checked_sub(amount)
+                    .ok_or(SaleError::MathOverflow)?;
+                campaign.payment_vault
+            }
+            ReferralReward::BonusTokens => {
+                campaign.referral_tokens_owed = campaign
+                    .referral_tokens_owed
+                    .checked_sub(amount)
+                    .ok_or(SaleError::MathOverflow)?;
+                campaign.token_vault
+            }
+        };
+
+        if vault == Pubkey::default() {
+            // Lamport commission held by the campaign account itself
+            payout::send_lamports(
+                &campaign.to_account_info(),
+                &ctx.accounts.referrer.to_account_info(),
+                amount,
+            )?;
+        } else {
+            let (Some(reward_vault), Some(reward_mint), Some(to), Some(token_program)) = (
+                &ctx.accounts.reward_vault,
+                &ctx.accounts.reward_mint,
+                &ctx.accounts.referrer_token_account,
+                &ctx.accounts.token_program,
+            ) else {
+                return err!(SaleError::InvalidRewardAccounts);
+            };
+            require_keys_eq!(reward_vault.key(), vault, SaleError::InvalidRewardAccounts);
+            require_keys_eq!(
+                reward_mint.key(),
+                reward_vault.mint,
+                SaleError::InvalidRewardAccounts
+            );
+            require_keys_eq!(to.mint, reward_mint.key(), SaleError::InvalidRewardAccounts);
+            require_keys_eq!(to.owner, stats.referrer, SaleError::InvalidRewardAccounts);
+            payout::send_from_vault(
+                campaign,
+                reward_vault,
+                reward_mint,
+                to,
+                token_program,
+                amount,
+            )?;
+        }
+
+        emit!(ReferralRewardsClaimed {
+            campaign: campaign.key(),
+            referrer: stats.referrer,
+            amount,
+            total_claimed: stats.rewards_claimed,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Donate to a campaign
+    pub fn donate(ctx: Context<Donate>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        purchase::check_public(&ctx.accounts.campaign, now)?;
+        ctx.accounts.purchase(amount, ctx.bumps.position)
+    }
+
+    // Donate during a whitelisted round, proving membership with a Merkle proof.
+    // `allocation` is the contribution cap encoded in the buyer's leaf, 0 for none.
+    pub fn donate_whitelisted(
+        ctx: Context<Donate>,
+        amount: u64,
+        allocation: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        purchase::check_whitelisted(
+            &ctx.accounts.campaign,
+            &ctx.accounts.position,
+            &ctx.accounts.user.key(),
+            amount,
+            allocation,
+            &proof,
+            now,
+        )?;
+        ctx.accounts.purchase(amount, ctx.bumps.position)
+    }
+
+    // Switches a campaign that has not opened yet to payments in an SPL mint;
+    // `terms` restates its amounts in the mint's base units
+    pub fn configure_spl_payment(
+        ctx: Context<ConfigureSplPayment>,
+        terms: PaymentTerms,
+    ) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(
+            campaign.status == CampaignStatus::Draft,
+            SaleError::SaleAlreadyOpened
+        );
+        require_keys_neq!(
+            ctx.accounts.payment_mint.key(),
+            campaign.token_mint,
+            SaleError::InvalidPaymentMint
+        );
+        // Rebates are paid out of the campaign's lamports, and USD prices are
+        // converted with a SOL/USD feed
+        require!(
+            !campaign.usd_pricing.is_enabled(),
+            SaleError::InvalidPaymentMint
+        );
+        require!(
+            !matches!(
+                campaign.pricing_mode,
+                PricingMode::DutchAuction {
+                    uniform_clearing: true,
+                    ..
+                }
+            ),
+            SaleError::InvalidPaymentMint
+        );
+        // The amounts were set in lamports; the admin restates them in the
+        // mint's units and they are checked as a new campaign would be
+        let args = terms.apply(campaign)?;
+        args.validate()?;
+        campaign.target_amount = args.target_amount;
+        campaign.hard_cap = args.hard_cap;
+        campaign.token_price = args.token_price;
+        campaign.min_contribution = args.min_contribution;
+        campaign.max_contribution_per_wallet = args.max_contribution_per_wallet;
+        campaign.pricing_mode = args.pricing_mode;
+        for (round, config) in campaign.rounds.iter_mut().zip(&args.rounds) {
+            round.config.token_price = config.token_price;
+        }
+        campaign.payment_mint = ctx.accounts.payment_mint.key();
+        campaign.payment_vault = ctx.accounts.payment_vault.key();
+        campaign.payment_decimals = ctx.accounts.payment_mint.decimals;
+
+        emit!(SplPaymentConfigured {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            payment_mint: campaign.payment_mint,
+            payment_vault: campaign.payment_vault,
+            terms,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Prices the tokens in micro-dollars instead of lamports. Each purchase
+    // then converts `usd_price` with `price_feed`, a SOL/USD feed owned by the
+    // protocol's oracle program, and the quote lands in `last_price`. Target
+    // and hard cap stay in lamports, and `token_price` keeps the lamport price
+    // they were validated against.
+    pub fn configure_usd_pricing(
+        ctx: Context<ConfigureUsdPricing>,
+        usd_price: u64,
+        max_price_age: i64,
+        max_confidence_bps: u16,
+    ) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(
+            campaign.status == CampaignStatus::Draft,
+            SaleError::SaleAlreadyOpened
+        );
+        require!(
+            campaign.payment_mint == Pubkey::default()
+                && campaign.pricing_mode == PricingMode::Fixed
+                && campaign.rounds.is_empty(),
+            SaleError::InvalidUsdPricing
+        );
+        let oracle_program = ctx.accounts.protocol_config.oracle_program;
+        require_keys_neq!(
+            oracle_program,
+            Pubkey::default(),
+            SaleError::InvalidPriceFeed
+        );
+        oracle::check_feed(&ctx.accounts.price_feed, &oracle_program)?;
+
+        let usd_pricing = UsdPricing {
+            price_feed: ctx.accounts.price_feed.key(),
+            oracle_program,
+            usd_price,
+            max_price_age,
+            max_confidence_bps,
+        };
+        usd_pricing.validate()?;
+        campaign.usd_pricing = usd_pricing;
+
+        emit!(UsdPricingConfigured {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            usd_pricing,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Donate to a campaign priced in its payment mint
+    pub fn donate_spl(ctx: Context<DonateSpl>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        purchase::check_public(&ctx.accounts.campaign, now)?;
+        ctx.accounts.purchase(amount, ctx.bumps.position)
+    }
+
+    // Whitelisted variant of `donate_spl`
+    pub fn donate_spl_whitelisted(
+        ctx: Context<DonateSpl>,
+        amount: u64,
+        allocation: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        purchase::check_whitelisted(
+            &ctx.accounts.campaign,
+            &ctx.accounts.position,
+            &ctx.accounts.user.key(),
+            amount,
+            allocation,
+            &proof,
+            now,
+        )?;
+        ctx.accounts.purchase(amount, ctx.bumps.position)
+    }
+
+    // Replaces the whitelist root; all zeroes opens the sale to everyone
+    pub fn set_whitelist_root(ctx: Context<SetWhitelistRoot>, root: [u8; 32]) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(!campaign.status.is_settled(), SaleError::AlreadyFinalized);
+        campaign.whitelist_root = root;
+
+        emit!(WhitelistRootUpdated {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            whitelist_root: root,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Lets buyers start claiming once the sale has succeeded
+    pub fn enable_claim(ctx: Context<EnableClaim>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        require!(campaign.status.is_successful(), SaleError::SaleNotSucceeded);
+        campaign.tge_ts = Clock::get()?.unix_timestamp;
+        status::transition(campaign, CampaignStatus::Claiming, campaign.tge_ts)?;
+
+        emit!(ClaimEnabled {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            tge_ts: campaign.tge_ts,
+        });
+        Ok(())
+    }
+
+    // Sends the buyer's vested, unclaimed tokens from the vault to their token account
+    pub fn claim(ctx: Context<Claim>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        let position = &mut ctx.accounts.position;
+        require!(!campaign.paused.claims, SaleError::ActionPaused);
+        require!(
+            campaign.status == CampaignStatus::Claiming,
+            SaleError::ClaimNotEnabled
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let vested =
+            campaign
+                .vesting
+                .vested_amount(position.tokens_bought, campaign.tge_ts, now)?;
+        let claimable = vested
+            .checked_sub(position.tokens_claimed)
+            .ok_or(SaleError::MathOverflow)?;
+        require!(claimable > 0, SaleError::NothingToClaim);
+
+        // Mark the vested amount as claimed so a repeat call has nothing to send
+        position.tokens_claimed = vested;
+        campaign.tokens_claimed = campaign
+            .tokens_claimed
+            .checked_add(claimable)
+            .ok_or(SaleError::MathOverflow)?;
+
+        // Uniform-clearing auctions return what the buyer paid above the
+        // clearing price with their first claim
+        let mut rebate = 0;
+        if campaign.rebates_owed > 0 && !position.rebate_paid {
+            let owed = pricing::payment_for_tokens(
+                position.tokens_bought,
+                campaign.clearing_price,
+                campaign.token_decimals,
+            )?;
+            rebate = position
+                .contributed
+                .saturating_sub(owed)
+                .min(campaign.rebates_owed);
+            position.rebate_paid = true;
+            campaign.rebates_owed -= rebate;
+            payout::send_lamports(
+                &campaign.to_account_info(),
+                &ctx.accounts.buyer.to_account_info(),
+                rebate,
+            )?;
+        }
+
+        payout::send_from_vault(
+            campaign,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_mint,
+            &ctx.accounts.buyer_token_account,
+            &ctx.accounts.token_program,
+            claimable,
+        )?;
+
+        emit!(TokensClaimed {
+            campaign: campaign.key(),
+            buyer: position.buyer,
+            amount: claimable,
+            rebate,
+            buyer_claimed: position.tokens_claimed,
+            total_claimed: campaign.tokens_claimed,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Sends the admin every vault token not owed to buyers once the sale is settled
+    pub fn reclaim_unsold_tokens(ctx: Context<ReclaimUnsoldTokens>) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        require!(!campaign.paused.withdrawals, SaleError::ActionPaused);
+        let owed = match campaign.status {
+            CampaignStatus::Succeeded | CampaignStatus::Claiming => campaign
+                .tokens_sold
+                .checked_sub(campaign.tokens_claimed)
+                .and_then(|owed| owed.checked_add(campaign.referral_tokens_owed))
+                .ok_or(SaleError::MathOverflow)?,
+            CampaignStatus::Failed | CampaignStatus::Cancelled => 0,
+            _ => return err!(SaleError::SaleStillActive),
+        };
+        let unsold = ctx.accounts.token_vault.amount.saturating_sub(owed);
+        require!(unsold > 0, SaleError::NothingToClaim);
+
+        payout::send_from_vault(
+            campaign,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_mint,
+            &ctx.accounts.admin_token_account,
+            &ctx.accounts.token_program,
+            unsold,
+        )?;
+
+        emit!(UnsoldTokensReclaimed {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            amount: unsold,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Returns a buyer's lamports when the sale missed its target or was cancelled
+    pub fn refund(ctx: Context<Refund>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        let position = &mut ctx.accounts.position;
+        require!(campaign.status.is_refundable(), SaleError::SaleNotFailed);
+        require!(
+            campaign.payment_mint == Pubkey::default(),
+            SaleError::WrongPaymentMethod
+        );
+        require!(
+            !position.refunded && position.contributed > 0,
+            SaleError::NothingToRefund
+        );
+
+        let amount = position.contributed;
+        position.refunded = true;
+        payout::send_lamports(
+            &campaign.to_account_info(),
+            &ctx.accounts.buyer.to_account_info(),
+            amount,
+        )?;
+        campaign.amount_refunded = campaign
+            .amount_refunded
+            .checked_add(amount)
+            .ok_or(SaleError::MathOverflow)?;
+
+        emit!(Refunded {
+            campaign: campaign.key(),
+            buyer: position.buyer,
+            amount,
+            total_refunded: campaign.amount_refunded,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Returns a buyer's payment-mint contribution when the sale missed its
+    // target or was cancelled
+    pub fn refund_spl(ctx: Context<RefundSpl>) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        let position = &mut ctx.accounts.position;
+        require!(campaign.status.is_refundable(), SaleError::SaleNotFailed);
+        require!(
+            !position.refunded && position.contributed > 0,
+            SaleError::NothingToRefund
+        );
+
+        let amount = position.contributed;
+        position.refunded = true;
+        payout::send_from_vault(
+            campaign,
+            &ctx.accounts.payment_vault,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.buyer_payment_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.amount_refunded = campaign
+            .amount_refunded
+            .checked_add(amount)
+            .ok_or(SaleError::MathOverflow)?;
+
+        emit!(Refunded {
+            campaign: campaign.key(),
+            buyer: ctx.accounts.buyer.key(),
+            amount,
+            total_refunded: campaign.amount_refunded,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Returns a settled buyer position's rent to the buyer. Anyone may call
+    // it, so an admin can clear positions before closing the campaign.
+    pub fn close_position(ctx: Context<ClosePosition>) -> Result<()> {
+        let campaign = &mut ctx.accounts.campaign;
+        let position = &ctx.accounts.position;
+        require!(
+            position.is_settled(campaign.status),
+            SaleError::PositionNotSettled
+        );
+        campaign.open_positions = campaign
+            .open_positions
+            .checked_sub(1)
+            .ok_or(SaleError::MathOverflow)?;
+        if campaign.open_positions == 0 {
+            // Every buyer has had their rebate, rounded in the campaign's
+            // favour; what is left is dust that withdraw may take
+            campaign.rebates_owed = 0;
+        }
+
+        emit!(PositionClosed {
+            campaign: campaign.key(),
+            buyer: position.buyer,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Closes a campaign whose every obligation is settled: no open positions,
+    // nothing owed to buyers or referrers and no funds or tokens left behind.
+    // Rent from the campaign and its vaults goes to the admin.
+    pub fn close_campaign(ctx: Context<CloseCampaign>) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        require!(campaign.status.is_settled(), SaleError::SaleStillActive);
+        require!(
+            campaign.open_positions == 0 && campaign.rebates_owed == 0,
+            SaleError::ObligationsOutstanding
+        );
+        // Referrers are only paid by a successful sale; otherwise their
+        // commission went back to the buyers with the refunds
+        require!(
+            !campaign.status.is_successful()
+                || (campaign.referral_payment_owed == 0 && campaign.referral_tokens_owed == 0),
+            SaleError::ObligationsOutstanding
+        );
+        // Raised funds leave through withdraw so the fee and signers apply;
+        // lamports left on a failed or cancelled campaign are only dust
+        require!(
+            !campaign.status.is_successful() || payout::withdrawable_lamports(campaign)? == 0,
+            SaleError::ObligationsOutstanding
+        );
+        require!(
+            ctx.accounts.token_vault.amount == 0,
+            SaleError::ObligationsOutstanding
+        );
+
+        let mut vaults = vec![ctx.accounts.token_vault.to_account_info()];
+        if campaign.payment_vault != Pubkey::default() {
+            let payment_vault = ctx
+                .accounts
+                .payment_vault
+                .as_ref()
+                .ok_or(SaleError::PaymentVaultRequired)?;
+            require_keys_eq!(
+                payment_vault.key(),
+                campaign.payment_vault,
+                SaleError::PaymentVaultRequired
+            );
+            require!(payment_vault.amount == 0, SaleError::ObligationsOutstanding);
+            vaults.push(payment_vault.to_account_info());
+        }
+        campaign.with_signer(|signer| {
+            for vault in vaults {
+                token::close_account(CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    CloseAccount {
+                        account: vault,
+                        destination: ctx.accounts.admin.to_account_info(),
+                        authority: campaign.to_account_info(),
+                    },
+                    signer,
+                ))?;
+            }
+            Ok(())
+        })?;
+
+        let closed = campaign.key();
+        ctx.accounts
+            .registry
+            .campaigns
+            .retain(|listed| *listed != closed);
+
+        emit!(CampaignClosed {
+            campaign: campaign.key(),
+            admin: ctx.accounts.admin.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Get the campaign's headline figures; the full account is too large for
+    // return data
+    pub fn get_campaign(ctx: Context<GetCampaign>) -> Result<CampaignSummary> {
+        let campaign = &ctx.accounts.campaign;
+        Ok(CampaignSummary {
+            admin: campaign.admin,
+            creator: campaign.creator,
+            index: campaign.index,
+            token_mint: campaign.token_mint,
+            payment_mint: campaign.payment_mint,
+            status: campaign.status,
+            target_amount: campaign.target_amount,
+            hard_cap: campaign.hard_cap,
+            amount_donated: campaign.amount_donated,
+            amount_withdrawn: campaign.amount_withdrawn,
+            amount_refunded: campaign.amount_refunded,
+            total_tokens: campaign.total_tokens,
+            tokens_sold: campaign.tokens_sold,
+            tokens_claimed: campaign.tokens_claimed,
+            token_price: campaign.token_price,
+            last_price: campaign.last_price,
+            start_ts: campaign.start_ts,
+            end_ts: campaign.end_ts,
+        })
+    }
+
+    // Get tokens bought for a specific user, 0 when they have never bought
+    pub fn get_tokens_bought(ctx: Context<GetTokensBought>) -> Result<u64> {
+        let info = &ctx.accounts.position;
+        if info.owner != &crate::ID || info.data_is_empty() {
+            return Ok(0);
+        }
+        let position = BuyerPosition::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        Ok(position.tokens_bought)
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitRegistry<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = AdminRegistry::space(0),
+        seeds = [b"REGISTRY".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub registry: Account<'info, AdminRegistry>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Create<'info> {
+    // Grows by one entry for the new campaign
+    #[account(
+        mut,
+        seeds = [b"REGISTRY".as_ref(), user.key().as_ref()],
+        bump = registry.bump,
+        realloc = AdminRegistry::space(registry.campaigns.len() + 1),
+        realloc::payer = user,
+        realloc::zero = false,
+    )]
+    pub registry: Account<'info, AdminRegistry>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Campaign::INIT_SPACE,
+        seeds = [
+            b"CROWDFUND".as_ref(),
+            user.key().as_ref(),
+            registry.next_index.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_mint: Account<'info, Mint>,
+    // The campaign address is predictable, so its ATA may already exist;
+    // creating it must not fail when someone got there first
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = token_mint,
+        associated_token::authority = campaign,
+    )]
+    pub token_vault: Account<'info, TokenAccount>,
+    #[account(seeds = [b"PROTOCOL".as_ref()], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositTokens<'info> {
+    #[account(
+        has_one = admin @ SaleError::Unauthorized,
+        has_one = token_mint,
+        has_one = token_vault,
+    )]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = admin,
+    )]
+    pub admin_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub token_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct OpenSale<'info> {
+    #[account(
+        mut,
+        has_one = admin @ SaleError::Unauthorized,
+        has_one = token_vault,
+    )]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+    pub token_vault: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct ActivateSale<'info> {
+    #[account(mut)]
+    pub campaign: Account<'info, Campaign>,
+}
+
+#[derive(Accounts)]
+pub struct CancelCampaign<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetGuardian<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPause<'info> {
+    #[account(
+        mut,
+        constraint = authority.key() == campaign.admin
+            || authority.key() == campaign.guardian @ SaleError::Unauthorized,
+    )]
+    pub campaign: Account<'info, Campaign>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAdmin<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(mut)]
+    pub campaign: Account<'info, Campaign>,
+    pub new_admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendSale<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Finalize<'info> {
+    #[account(mut)]
+    pub campaign: Account<'info, Campaign>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(mut, has_one = treasury)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: only receives lamports; must be the campaign treasury
+    #[account(mut)]
+    pub treasury: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"PROTOCOL".as_ref()],
+        bump = protocol_config.bump,
+        has_one = fee_recipient,
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    /// CHECK: only receives lamports; must be the protocol fee recipient
+    #[account(mut)]
+    pub fee_recipient: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTreasury<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitProtocolConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ProtocolConfig::INIT_SPACE,
+        seeds = [b"PROTOCOL".as_ref()],
+        bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::SmartContracts>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ SaleError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProtocolConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"PROTOCOL".as_ref()],
+        bump = protocol_config.bump,
+        has_one = admin @ SaleError::Unauthorized,
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawalSigners<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeWithdrawal<'info> {
+    #[account(mut)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + WithdrawalProposal::INIT_SPACE,
+        seeds = [
+            b"WITHDRAWAL".as_ref(),
+            campaign.key().as_ref(),
+            campaign.proposal_count.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub proposal: Account<'info, WithdrawalProposal>,
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveWithdrawal<'info> {
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        mut,
+        seeds = [
+            b"WITHDRAWAL".as_ref(),
+            campaign.key().as_ref(),
+            proposal.index.to_le_bytes().as_ref(),
+        ],
+        bump = proposal.bump,
+        has_one = campaign,
+    )]
+    pub proposal: Account<'info, WithdrawalProposal>,
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteWithdrawal<'info> {
+    #[account(mut)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        mut,
+        seeds = [
+            b"WITHDRAWAL".as_ref(),
+            campaign.key().as_ref(),
+            proposal.index.to_le_bytes().as_ref(),
+        ],
+        bump = proposal.bump,
+        has_one = campaign,
+        has_one = recipient,
+    )]
+    pub proposal: Account<'info, WithdrawalProposal>,
+    /// CHECK: only receives lamports; must match the proposal's recipient
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"PROTOCOL".as_ref()],
+        bump = protocol_config.bump,
+        has_one = fee_recipient,
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    /// CHECK: only receives lamports; must be the protocol fee recipient
+    #[account(mut)]
+    pub fee_recipient: UncheckedAccount<'info>,
+}
 
-    // Creates a campaign
-pub fn create(ctx: Context<Create>) -> ProgramResult {
-        let campaign = &mut ctx.accounts.campaign;
+#[derive(Accounts)]
+pub struct ExecuteWithdrawalSpl<'info> {
+    #[account(mut, has_one = payment_mint, has_one = payment_vault)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        mut,
+        seeds = [
+            b"WITHDRAWAL".as_ref(),
+            campaign.key().as_ref(),
+            proposal.index.to_le_bytes().as_ref(),
+        ],
+        bump = proposal.bump,
+        has_one = campaign,
+    )]
+    pub proposal: Account<'info, WithdrawalProposal>,
+    pub payment_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub payment_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = proposal.recipient,
+    )]
+    pub recipient_payment_account: Account<'info, TokenAccount>,
+    #[account(seeds = [b"PROTOCOL".as_ref()], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = protocol_config.fee_recipient,
+    )]
+    pub fee_payment_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
 
-        // Hardcoded values for the campaign
-        campaign.admin = *ctx.accounts.user.key;
-        // Store target amount, total tokens, and token price in lamports
-        campaign.target_amount = 10 * 1_000_000; // 10 SOL converted to lamports
-        campaign.amount_donated = 0;
-        campaign.amount_withdrawn = 0;
-        campaign.total_tokens = 100 * 1_000_000; // 100 tokens converted to lamports
-        campaign.token_price = 100_000; // 0.1 SOL (100_000 lamports) per token
-        // Initialize tokens_sold and sale_ongoing
-        campaign.tokens_sold = 0;
-        campaign.sale_ongoing = true; // Sale is ongoing initially
+#[derive(Accounts)]
+pub struct ConfigureUsdPricing<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+    #[account(seeds = [b"PROTOCOL".as_ref()], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    /// CHECK: owner and layout are checked by `oracle::check_feed`
+    pub price_feed: UncheckedAccount<'info>,
+}
 
-        Ok(())
-    }
+#[derive(Accounts)]
+pub struct ConfigureReferrals<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+}
 
-    // Withdraw from a campaign
-pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> ProgramResult {
-        let campaign = &mut ctx.accounts.campaign;
-        let user = &mut ctx.accounts.user;
-        // Restricts Withdrawal to campaign admin
-        if campaign.admin != *user.key {
-            return Err(ProgramError::IncorrectProgramId);
-        }
-        let rent_balance = Rent::get()?.minimum_balance(campaign.to_account_info().data_len());
-        if **campaign.to_account_info().lamports.borrow() - rent_balance < amount {
-            return Err(ProgramError::InsufficientFunds);
-        }
-        **campaign.to_account_info().try_borrow_mut_lamports()? -= amount;
-        **user.to_account_info().try_borrow_mut_lamports()? += amount;
-        (&mut ctx.accounts.campaign).amount_withdrawn += amount;
-        Ok(())
-    }
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        init,
+        payer = referrer,
+        space = 8 + ReferrerStats::INIT_SPACE,
+        seeds = [b"REFERRER".as_ref(), campaign.key().as_ref(), referrer.key().as_ref()],
+        bump
+    )]
+    pub referrer_stats: Account<'info, ReferrerStats>,
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
 
-// Donate to a campaign
-pub fn donate(ctx: Context<Donate>, amount: u64) -> ProgramResult {
-    let mut campaign = ctx.accounts.campaign.clone();
-    let user = &ctx.accounts.user;
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(mut)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        mut,
+        seeds = [b"REFERRER".as_ref(), campaign.key().as_ref(), referrer.key().as_ref()],
+        bump = referrer_stats.bump,
+        has_one = campaign,
+        has_one = referrer,
+    )]
+    pub referrer_stats: Account<'info, ReferrerStats>,
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+    // Token payouts only; checked against the campaign in the handler
+    #[account(mut)]
+    pub reward_vault: Option<Account<'info, TokenAccount>>,
+    pub reward_mint: Option<Account<'info, Mint>>,
+    #[account(mut)]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
 
-    let tokens_left = campaign.total_tokens - campaign.tokens_sold;
-    if tokens_left == 0 {
-        campaign.sale_ongoing = false; // Stop the sale if all tokens are sold
-        return Err(ProgramError::Custom(1001)); // Custom error code to indicate sale ended
-    }
+#[derive(Accounts)]
+pub struct Donate<'info> {
+    #[account(mut)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + BuyerPosition::INIT_SPACE,
+        seeds = [b"POSITION".as_ref(), campaign.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, BuyerPosition>,
+    // Referrer's stats for this campaign, when the purchase was referred
+    #[account(mut, has_one = campaign)]
+    pub referrer_stats: Option<Account<'info, ReferrerStats>>,
+    /// CHECK: required for USD-priced campaigns, checked by `oracle::read_price`
+    pub price_feed: Option<UncheckedAccount<'info>>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
 
-    let tokens_to_buy = amount * campaign.token_price;
-    if tokens_to_buy > tokens_left {
-        // Refund excess funds to the user
-        let excess_funds = (tokens_to_buy - tokens_left) / campaign.token_price;
-        let refund_ix = anchor_lang::solana_program::system_instruction::transfer(
-            &ctx.accounts.campaign.key(),
-            &ctx.accounts.user.key(),
-            excess_funds,
+impl<'info> Donate<'info> {
+    // Shared lamport purchase path for public and whitelisted donations
+    fn purchase(&mut self, amount: u64, position_bump: u8) -> Result<()> {
+        require!(
+            self.campaign.payment_mint == Pubkey::default(),
+            SaleError::WrongPaymentMethod
+        );
+        let usd_pricing = self.campaign.usd_pricing;
+        let mut quote = None;
+        if usd_pricing.is_enabled() {
+            // Re-quote the lamport price from the feed before every purchase
+            let feed = self
+                .price_feed
+                .as_ref()
+                .ok_or(SaleError::InvalidPriceFeed)?;
+            let now = Clock::get()?.unix_timestamp;
+            let sol = oracle::read_price(feed, &usd_pricing, now)?;
+            let token_price = oracle::lamports_per_token(usd_pricing.usd_price, &sol)?;
+            quote = Some(token_price);
+
+            emit!(PriceQuoted {
+                campaign: self.campaign.key(),
+                usd_price: usd_pricing.usd_price,
+                sol_price: sol.price,
+                sol_expo: sol.expo,
+                publish_time: sol.publish_time,
+                token_price,
+                timestamp: now,
+            });
+        }
+        let charged = purchase::record(
+            &mut self.campaign,
+            &mut self.position,
+            self.referrer_stats.as_mut(),
+            self.user.key(),
+            position_bump,
+            amount,
+            quote,
+        )?;
+
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &self.user.key(),
+            &self.campaign.key(),
+            charged,
         );
         anchor_lang::solana_program::program::invoke(
-            &refund_ix,
-            &[ctx.accounts.campaign.to_account_info(), ctx.accounts.user.to_account_info()],
+            &ix,
+            &[self.user.to_account_info(), self.campaign.to_account_info()],
         )?;
-        campaign.sale_ongoing = false; // Stop the sale if all tokens are sold
-        return Err(ProgramError::Custom(1003)); // Custom error code to indicate overpayment and sale ended
-    }
 
-    let mut user_tokens_updated = false;
-    for user_token in &mut campaign.user_tokens {
-        if user_token.0 == *user.key {
-            user_token.1 += tokens_to_buy; // Update user's tokens bought
-            user_tokens_updated = true;
-            break;
-        }
+        Ok(())
     }
+}
 
-    if !user_tokens_updated {
-        campaign.user_tokens.push((*user.key, tokens_to_buy)); // Store user's Pubkey and tokens bought
-    }
+#[derive(Accounts)]
+pub struct DonateSpl<'info> {
+    #[account(mut, has_one = payment_mint, has_one = payment_vault)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + BuyerPosition::INIT_SPACE,
+        seeds = [b"POSITION".as_ref(), campaign.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, BuyerPosition>,
+    // Referrer's stats for this campaign, when the purchase was referred
+    #[account(mut, has_one = campaign)]
+    pub referrer_stats: Option<Account<'info, ReferrerStats>>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub payment_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = user,
+    )]
+    pub user_payment_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub payment_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
 
+impl<'info> DonateSpl<'info> {
+    // Shared payment-mint purchase path for public and whitelisted donations
+    fn purchase(&mut self, amount: u64, position_bump: u8) -> Result<()> {
+        let charged = purchase::record(
+            &mut self.campaign,
+            &mut self.position,
+            self.referrer_stats.as_mut(),
+            self.user.key(),
+            position_bump,
+            amount,
+            None,
+        )?;
 
-    let ix = anchor_lang::solana_program::system_instruction::transfer(
-        &ctx.accounts.user.key(),
-        &ctx.accounts.campaign.key(),
-        amount,
-    );
-    let result = anchor_lang::solana_program::program::invoke(
-        &ix,
-        &[ctx.accounts.user.to_account_info(), ctx.accounts.campaign.to_account_info()],
-    );
-    if let Err(e) = result {
-        return Err(e.into());
+        let cpi_accounts = TransferChecked {
+            from: self.user_payment_account.to_account_info(),
+            mint: self.payment_mint.to_account_info(),
+            to: self.payment_vault.to_account_info(),
+            authority: self.user.to_account_info(),
+        };
+        token::transfer_checked(
+            CpiContext::new(self.token_program.to_account_info(), cpi_accounts),
+            charged,
+            self.payment_mint.decimals,
+        )
     }
-    
-    campaign.tokens_sold += tokens_to_buy;
-    campaign.amount_donated += amount;
+}
 
-    if campaign.tokens_sold == campaign.total_tokens {
-        campaign.sale_ongoing = false; // Stop the sale if all tokens are sold
-    }
+#[derive(Accounts)]
+pub struct ConfigureSplPayment<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub payment_mint: Account<'info, Mint>,
+    // May already have been created by anyone, like the token vault
+    #[account(
+        init_if_needed,
+        payer = admin,
+        associated_token::mint = payment_mint,
+        associated_token::authority = campaign,
+    )]
+    pub payment_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
 
-    Ok(())
+#[derive(Accounts)]
+pub struct WithdrawSpl<'info> {
+    #[account(
+        mut,
+        has_one = admin @ SaleError::Unauthorized,
+        has_one = payment_mint,
+        has_one = payment_vault,
+    )]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+    pub payment_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub payment_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = campaign.treasury,
+    )]
+    pub treasury_payment_account: Account<'info, TokenAccount>,
+    #[account(seeds = [b"PROTOCOL".as_ref()], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = protocol_config.fee_recipient,
+    )]
+    pub fee_payment_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
 }
 
-    // Get the campaign
-pub fn get_campaign(ctx: Context<GetCampaign>) -> Result<(Pubkey, u64, u64, u64, u64, u64, u64, bool, Vec<(Pubkey, u64)>)> {
-        let campaign = &ctx.accounts.campaign;
-        let _user = &ctx.accounts.user;
-        // Declare local variables to store campaign field values
-        let admin = campaign.admin;
-        let target_amount = campaign.target_amount;
-        let amount_donated = campaign.amount_donated;
-        let amount_withdrawn = campaign.amount_withdrawn;
-        let total_tokens = campaign.total_tokens;
-        let token_price = campaign.token_price;
-        let tokens_sold = campaign.tokens_sold;
-        let sale_ongoing = campaign.sale_ongoing;
-        let user_tokens = campaign.user_tokens.clone();
-    
-        // Return the values as a tuple
-        Ok((
-            admin,
-            target_amount,
-            amount_donated,
-            amount_withdrawn,
-            total_tokens,
-            token_price,
-            tokens_sold,
-            sale_ongoing,
-            user_tokens,
-        ))
-    }
-    
-    
-
-// Get tokens bought for a specific user
-pub fn get_tokens_bought(ctx: Context<GetTokensBought>) -> Result<u64> {
-    let campaign = &ctx.accounts.campaign;
-    let user = &ctx.accounts.user;
-
-    let mut tokens_bought = 0;
-    for user_token in &campaign.user_tokens {
-        if user_token.0 == *user.key {
-            tokens_bought = user_token.1;
-            break;
-        }
-    }
+#[derive(Accounts)]
+pub struct SetWhitelistRoot<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+}
 
-    Ok(tokens_bought)
+#[derive(Accounts)]
+pub struct EnableClaim<'info> {
+    #[account(mut, has_one = admin @ SaleError::Unauthorized)]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct Claim<'info> {
+    #[account(mut, has_one = token_mint, has_one = token_vault)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        mut,
+        seeds = [b"POSITION".as_ref(), campaign.key().as_ref(), buyer.key().as_ref()],
+        bump = position.bump,
+        has_one = campaign,
+        has_one = buyer,
+    )]
+    pub position: Account<'info, BuyerPosition>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    pub token_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub token_vault: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = token_mint,
+        associated_token::authority = buyer,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+pub struct ReclaimUnsoldTokens<'info> {
+    #[account(
+        has_one = admin @ SaleError::Unauthorized,
+        has_one = token_mint,
+        has_one = token_vault,
+    )]
+    pub campaign: Account<'info, Campaign>,
+    pub admin: Signer<'info>,
+    pub token_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub token_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = admin,
+    )]
+    pub admin_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
 
+#[derive(Accounts)]
+pub struct ClosePosition<'info> {
+    #[account(mut)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [b"POSITION".as_ref(), campaign.key().as_ref(), buyer.key().as_ref()],
+        bump = position.bump,
+        has_one = campaign,
+        has_one = buyer,
+    )]
+    pub position: Account<'info, BuyerPosition>,
+    /// CHECK: only receives the position's rent; must be the position's buyer
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
 }
+
 #[derive(Accounts)]
-pub struct Create<'info> {
+pub struct CloseCampaign<'info> {
     #[account(
-        init,
-        payer = user,
-        space = 9000,
-        seeds = [b"CROWDFUND".as_ref(), user.key().as_ref()],
-        bump
+        mut,
+        close = admin,
+        has_one = admin @ SaleError::Unauthorized,
+        has_one = token_vault,
     )]
     pub campaign: Account<'info, Campaign>,
     #[account(mut)]
-    pub user: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"REGISTRY".as_ref(), campaign.creator.as_ref()],
+        bump = registry.bump,
+    )]
+    pub registry: Account<'info, AdminRegistry>,
+    #[account(mut)]
+    pub token_vault: Account<'info, TokenAccount>,
+    // Only for campaigns paid in an SPL mint
+    #[account(mut)]
+    pub payment_vault: Option<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct Withdraw<'info> {
+pub struct Refund<'info> {
     #[account(mut)]
     pub campaign: Account<'info, Campaign>,
+    #[account(
+        mut,
+        seeds = [b"POSITION".as_ref(), campaign.key().as_ref(), buyer.key().as_ref()],
+        bump = position.bump,
+        has_one = campaign,
+        has_one = buyer,
+    )]
+    pub position: Account<'info, BuyerPosition>,
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub buyer: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct Donate<'info> {
-    #[account(mut)]
+pub struct RefundSpl<'info> {
+    #[account(mut, has_one = payment_mint, has_one = payment_vault)]
     pub campaign: Account<'info, Campaign>,
+    #[account(
+        mut,
+        seeds = [b"POSITION".as_ref(), campaign.key().as_ref(), buyer.key().as_ref()],
+        bump = position.bump,
+        has_one = campaign,
+        has_one = buyer,
+    )]
+    pub position: Account<'info, BuyerPosition>,
+    pub buyer: Signer<'info>,
+    pub payment_mint: Account<'info, Mint>,
     #[account(mut)]
-    pub user: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub payment_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = buyer,
+    )]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
 pub struct GetCampaign<'info> {
-#[account(mut)]
-pub campaign: Account<'info, Campaign>,
-#[account(mut)]
-pub user: Signer<'info>,
+    #[account(mut)]
+    pub campaign: Account<'info, Campaign>,
+    #[account(mut)]
+    pub user: Signer<'info>,
 }
+
 #[derive(Accounts)]
 pub struct GetTokensBought<'info> {
     #[account(mut)]
@@ -205,18 +2156,398 @@ pub struct GetTokensBought<'info> {
     #[account(signer)]
     /// CHECK:
     pub user: AccountInfo<'info>,
+    /// CHECK: the user's position PDA, which only exists once they have bought
+    #[account(
+        seeds = [b"POSITION".as_ref(), campaign.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: UncheckedAccount<'info>,
+}
+
+// Parameters supplied by the admin when creating a campaign. Payment units are
+// lamports until configure_spl_payment restates the amounts for a payment mint.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CreateCampaignArgs {
+    pub target_amount: u64, // Soft target in payment units
+    pub hard_cap: u64,      // Most the sale may raise, in payment units
+    pub total_tokens: u64,  // Tokens for sale, in base units
+    pub token_price: u64,   // Payment units per whole token (10^decimals base units)
+    pub token_decimals: u8, // Decimals of the token being sold
+    pub start_ts: i64,      // Unix timestamp the sale opens
+    pub end_ts: i64,        // Unix timestamp the sale closes
+    pub vesting: VestingConfig,
+    pub whitelist_root: [u8; 32],         // All zeroes for a public sale
+    pub min_contribution: u64,            // Payment units a wallet must put in overall
+    pub max_contribution_per_wallet: u64, // Payment-unit cap per wallet, 0 for none
+    pub rounds: Vec<RoundConfig>,         // Empty for a single fixed-price sale
+    pub unsold_policy: UnsoldPolicy,
+    pub pricing_mode: PricingMode,
+}
+
+impl CreateCampaignArgs {
+    // Rejects campaigns that could never be filled or priced
+    pub fn validate(&self) -> Result<()> {
+        require!(self.token_price > 0, SaleError::InvalidPrice);
+        require!(self.total_tokens > 0, SaleError::InvalidTokenSupply);
+        require!(self.start_ts < self.end_ts, SaleError::InvalidSaleWindow);
+        self.vesting.validate()?;
+        require!(
+            self.min_contribution <= self.hard_cap
+                && (self.max_contribution_per_wallet == 0
+                    || (self.min_contribution <= self.max_contribution_per_wallet
+                        && self.max_contribution_per_wallet <= self.hard_cap)),
+            SaleError::InvalidContributionLimits
+        );
+        rounds::validate_rounds(&self.rounds, self.start_ts, self.end_ts, self.total_tokens)?;
+        self.pricing_mode
+            .validate(self.token_price, !self.rounds.is_empty())?;
+        // Payment units raised if every token is sold at its round's (or starting) price
+        let max_raise = if self.rounds.is_empty() {
+            pricing::sale_value(self.total_tokens, self.token_price, self.token_decimals)?
+        } else {
+            self.rounds.iter().try_fold(0u128, |total, round| {
+                let value = pricing::sale_value(
+                    round.token_allocation,
+                    round.token_price,
+                    self.token_decimals,
+                )?;
+                total
+                    .checked_add(value)
+                    .ok_or_else(|| error!(SaleError::MathOverflow))
+            })?
+        };
+        require!(
+            self.target_amount as u128 <= max_raise,
+            SaleError::TargetExceedsSupply
+        );
+        require!(
+            self.hard_cap >= self.target_amount && self.hard_cap as u128 <= max_raise,
+            SaleError::InvalidHardCap
+        );
+        Ok(())
+    }
+}
+
+// A campaign's amounts restated in payment-mint base units when it switches
+// from lamports to a payment mint
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PaymentTerms {
+    pub target_amount: u64,
+    pub hard_cap: u64,
+    pub token_price: u64,
+    pub floor_price: u64,       // Ignored unless the campaign is a Dutch auction
+    pub round_prices: Vec<u64>, // One per round, in round order
+    pub min_contribution: u64,
+    pub max_contribution_per_wallet: u64,
 }
 
+impl PaymentTerms {
+    // The campaign's creation parameters with these terms in place
+    pub fn apply(&self, campaign: &Campaign) -> Result<CreateCampaignArgs> {
+        require!(
+            self.round_prices.len() == campaign.rounds.len(),
+            SaleError::InvalidRounds
+        );
+        let pricing_mode = match campaign.pricing_mode {
+            PricingMode::Fixed => PricingMode::Fixed,
+            PricingMode::DutchAuction {
+                uniform_clearing, ..
+            } => PricingMode::DutchAuction {
+                floor_price: self.floor_price,
+                uniform_clearing,
+            },
+        };
+        Ok(CreateCampaignArgs {
+            target_amount: self.target_amount,
+            hard_cap: self.hard_cap,
+            total_tokens: campaign.total_tokens,
+            token_price: self.token_price,
+            token_decimals: campaign.token_decimals,
+            start_ts: campaign.start_ts,
+            end_ts: campaign.end_ts,
+            vesting: campaign.vesting,
+            whitelist_root: campaign.whitelist_root,
+            min_contribution: self.min_contribution,
+            max_contribution_per_wallet: self.max_contribution_per_wallet,
+            rounds: campaign
+                .rounds
+                .iter()
+                .zip(&self.round_prices)
+                .map(|(round, &token_price)| RoundConfig {
+                    token_price,
+                    ..round.config
+                })
+                .collect(),
+            unsold_policy: campaign.unsold_policy,
+            pricing_mode,
+        })
+    }
+}
 
 #[account]
+#[derive(InitSpace)]
 pub struct Campaign {
     pub admin: Pubkey,
+    pub creator: Pubkey,       // Campaign PDA seed, unchanged by admin transfers
+    pub index: u64,            // Campaign PDA seed, the creator's nth campaign
+    pub pending_admin: Pubkey, // Default pubkey when no transfer is pending
+    pub guardian: Pubkey,      // May pause alongside the admin, default pubkey when none
+    pub treasury: Pubkey,      // Receives withdrawals, the creator until changed
+    pub token_mint: Pubkey,    // Mint of the token being sold
+    pub token_vault: Pubkey,   // Campaign-owned ATA holding the sale tokens
+    pub payment_mint: Pubkey,  // Default pubkey when the sale is paid in lamports
+    pub payment_vault: Pubkey, // Campaign-owned ATA collecting payment-mint funds
+    pub payment_decimals: u8,
+    pub target_amount: u64,
+    pub hard_cap: u64,
+    pub amount_donated: u64,
+    pub amount_withdrawn: u64, // Gross, including protocol fees
+    pub amount_refunded: u64,
+    pub protocol_fee_bps: u16, // Copied from ProtocolConfig at create
+    pub protocol_fees_paid: u64,
+    pub total_tokens: u64,
+    pub token_price: u64, // Payment units (lamports or payment-mint base units) per whole token
+    pub token_decimals: u8,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub total_extension: i64, // Seconds added to end_ts by extend_sale
+    pub tokens_sold: u64,
+    pub tokens_claimed: u64,
+    pub status: CampaignStatus,
+    pub whitelist_root: [u8; 32], // Merkle root of approved buyers, zeroes when public
+    pub min_contribution: u64,
+    pub max_contribution_per_wallet: u64,
+    pub vesting: VestingConfig,
+    pub tge_ts: i64, // Set when claiming is enabled, start of the vesting schedule
+    #[max_len(MAX_ROUNDS)]
+    pub rounds: Vec<SaleRound>, // Empty for a single fixed-price sale
+    pub unsold_policy: UnsoldPolicy,
+    pub pricing_mode: PricingMode,
+    pub last_price: u64,     // Price charged by the most recent purchase
+    pub clearing_price: u64, // Uniform-clearing auction price, set by finalize
+    pub rebates_owed: u64,   // Lamports still due back to auction buyers
+    pub paused: PauseFlags,
+    #[max_len(MAX_PAUSE_REASON)]
+    pub pause_reason: String,
+    pub paused_at: i64, // When the current pause started, 0 when not paused
+    // Withdrawal approvers, empty when the admin withdraws alone
+    #[max_len(MAX_SIGNERS)]
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,       // Approvals a withdrawal proposal needs
+    pub proposal_count: u64, // Seeds the next WithdrawalProposal
+    pub referral: ReferralConfig,
+    pub referral_payment_owed: u64, // Unclaimed commission, in payment units
+    pub referral_tokens_owed: u64,  // Bonus tokens not yet claimed
+    // Bonus tokens granted so far, counted against the referral token budget
+    pub referral_tokens_credited: u64,
+    pub open_positions: u32, // Buyer positions not yet closed
+    pub usd_pricing: UsdPricing,
+    pub bump: u8,
+}
+
+impl Campaign {
+    // Books a withdrawal of `amount`, `fee` of which went to the protocol
+    pub fn record_withdrawal(&mut self, amount: u64, fee: u64) -> Result<()> {
+        self.amount_withdrawn = self
+            .amount_withdrawn
+            .checked_add(amount)
+            .ok_or(SaleError::MathOverflow)?;
+        self.protocol_fees_paid = self
+            .protocol_fees_paid
+            .checked_add(fee)
+            .ok_or(SaleError::MathOverflow)?;
+        Ok(())
+    }
+
+    // Runs `f` with the seeds the campaign PDA signs its CPIs with
+    pub fn with_signer<T>(&self, f: impl FnOnce(&[&[&[u8]]]) -> Result<T>) -> Result<T> {
+        let index = self.index.to_le_bytes();
+        let seeds: &[&[u8]] = &[b"CROWDFUND", self.creator.as_ref(), &index, &[self.bump]];
+        f(&[seeds])
+    }
+
+    // Index of the round open at `now`; None when the campaign has no rounds
+    pub fn active_round(&self, now: i64) -> Result<Option<usize>> {
+        if self.rounds.is_empty() {
+            return Ok(None);
+        }
+        self.rounds
+            .iter()
+            .position(|round| now >= round.config.start_ts && now < round.config.end_ts)
+            .map(Some)
+            .ok_or_else(|| error!(SaleError::NoActiveRound))
+    }
+
+    // Price per whole token outside of rounds at `now`
+    pub fn current_price(&self, now: i64) -> u64 {
+        match self.pricing_mode {
+            PricingMode::Fixed => self.token_price,
+            PricingMode::DutchAuction { floor_price, .. } => pricing::dutch_price(
+                self.token_price,
+                floor_price,
+                self.start_ts,
+                self.end_ts,
+                now,
+            ),
+        }
+    }
+
+    // Whitelist that applies to purchases made at `now`
+    pub fn whitelist_root_at(&self, now: i64) -> Result<[u8; 32]> {
+        Ok(match self.active_round(now)? {
+            Some(index) => self.rounds[index].config.whitelist_root,
+            None => self.whitelist_root,
+        })
+    }
+
+    // Tokens still on offer in round `index`, including earlier rounds'
+    // leftovers when they roll forward
+    pub fn round_tokens_left(&self, index: usize) -> u64 {
+        let mut carried = 0u64;
+        for round in &self.rounds[..index] {
+            carried = match self.unsold_policy {
+                UnsoldPolicy::RollForward => {
+                    (round.config.token_allocation + carried).saturating_sub(round.tokens_sold)
+                }
+                UnsoldPolicy::ReturnToAdmin => 0,
+            };
+        }
+        let round = &self.rounds[index];
+        (round.config.token_allocation + carried).saturating_sub(round.tokens_sold)
+    }
+}
+
+// Fixed-size view of a campaign returned by get_campaign
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CampaignSummary {
+    pub admin: Pubkey,
+    pub creator: Pubkey,
+    pub index: u64,
+    pub token_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub status: CampaignStatus,
     pub target_amount: u64,
+    pub hard_cap: u64,
     pub amount_donated: u64,
     pub amount_withdrawn: u64,
+    pub amount_refunded: u64,
     pub total_tokens: u64,
-    pub token_price: u64,
     pub tokens_sold: u64,
-    pub sale_ongoing: bool,
-    pub user_tokens: Vec<(Pubkey, u64)>, // Vector to store user tokens bought
+    pub tokens_claimed: u64,
+    pub token_price: u64,
+    pub last_price: u64,
+    pub start_ts: i64,
+    pub end_ts: i64,
+}
+
+// Campaigns created by one wallet, seeded with "REGISTRY" and the creator.
+// Admin transfers do not move a campaign between registries.
+#[account]
+pub struct AdminRegistry {
+    pub admin: Pubkey,
+    pub next_index: u64, // Index the creator's next campaign will use
+    pub campaigns: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl AdminRegistry {
+    // Account size holding `campaigns` entries
+    pub const fn space(campaigns: usize) -> usize {
+        8 + 32 + 8 + 4 + 32 * campaigns + 1
+    }
+}
+
+// Launchpad-wide fee and oracle settings, a single PDA seeded with "PROTOCOL"
+#[account]
+#[derive(InitSpace)]
+pub struct ProtocolConfig {
+    pub admin: Pubkey,
+    pub fee_bps: u16, // Applied to campaigns created after it is set
+    pub fee_recipient: Pubkey,
+    pub oracle_program: Pubkey, // Owner of accepted price feeds, default until set
+    pub bump: u8,
+}
+
+// One per buyer per campaign, created on the buyer's first purchase.
+// `campaign` and `buyer` sit at fixed offsets (8 and 40) so positions can be
+// listed with `getProgramAccounts` memcmp filters.
+#[account]
+#[derive(InitSpace)]
+pub struct BuyerPosition {
+    pub campaign: Pubkey,
+    pub buyer: Pubkey,
+    pub contributed: u64,    // Payment units paid in
+    pub tokens_bought: u64,  // Tokens owed, in base units
+    pub tokens_claimed: u64, // Tokens already delivered
+    pub first_purchase_ts: i64,
+    pub last_purchase_ts: i64,
+    pub refunded: bool,    // Contribution returned after a failed sale
+    pub rebate_paid: bool, // Uniform-clearing rebate already sent
+    pub bump: u8,
+}
+
+impl BuyerPosition {
+    // Nothing is left to deliver to the buyer under a campaign in `status`
+    pub fn is_settled(&self, status: CampaignStatus) -> bool {
+        if status.is_successful() {
+            self.tokens_claimed == self.tokens_bought
+        } else if status.is_refundable() {
+            self.refunded || self.contributed == 0
+        } else {
+            false
+        }
+    }
+}
+
+// One per referrer per campaign, created by `register_referrer`
+#[account]
+#[derive(InitSpace)]
+pub struct ReferrerStats {
+    pub campaign: Pubkey,
+    pub referrer: Pubkey,
+    pub referred_purchases: u64,
+    pub referred_volume: u64, // Payment units charged to referred buyers
+    pub rewards_earned: u64,  // Payment units or bonus tokens, per the campaign's config
+    pub rewards_claimed: u64,
+    pub bump: u8,
+}
+
+// A withdrawal waiting for the campaign's signer threshold, one per proposal
+#[account]
+#[derive(InitSpace)]
+pub struct WithdrawalProposal {
+    pub campaign: Pubkey,
+    pub index: u64, // Position in the campaign's proposal sequence
+    pub proposer: Pubkey,
+    pub amount: u64, // Lamports or payment-mint base units
+    pub recipient: Pubkey,
+    pub expires_at: i64,
+    #[max_len(MAX_SIGNERS)]
+    pub approvals: Vec<Pubkey>,
+    pub executed: bool,
+    pub bump: u8,
+}
+
+impl WithdrawalProposal {
+    // Still open for approvals at `now`
+    pub fn check_live(&self, now: i64) -> Result<()> {
+        require!(!self.executed, SaleError::ProposalExecuted);
+        require!(now < self.expires_at, SaleError::ProposalExpired);
+        Ok(())
+    }
+
+    // Live and approved by at least `threshold` of the current `signers`
+    pub fn check_approved(&self, now: i64, signers: &[Pubkey], threshold: u8) -> Result<()> {
+        self.check_live(now)?;
+        let approvals = self
+            .approvals
+            .iter()
+            .filter(|approver| signers.contains(approver))
+            .count();
+        require!(
+            threshold > 0 && approvals >= threshold as usize,
+            SaleError::ThresholdNotMet
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,20 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak::hashv;
+
+// Leaf committed to by the whitelist tree: keccak256(buyer || allocation_le)
+pub fn leaf(buyer: &Pubkey, allocation: u64) -> [u8; 32] {
+    hashv(&[buyer.as_ref(), &allocation.to_le_bytes()]).0
+}
+
+// Walks `proof` from `leaf` to the root, hashing each pair in sorted order so
+// proofs do not need to carry left/right positions
+pub fn verify(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            hashv(&[&node, sibling]).0
+        } else {
+            hashv(&[sibling, &node]).0
+        }
+    });
+    computed == root
+}
@@ -0,0 +1,27 @@
+use anchor_lang::prelude::*;
+
+use crate::SaleError;
+
+// Largest signer set a campaign can require approvals from
+pub const MAX_SIGNERS: usize = 10;
+
+// A signer set is either empty (admin withdraws alone) or distinct, real keys
+// with a threshold between 1 and the set size
+pub fn validate_signers(signers: &[Pubkey], threshold: u8) -> Result<()> {
+    if signers.is_empty() {
+        require!(threshold == 0, SaleError::InvalidSignerSet);
+        return Ok(());
+    }
+    require!(signers.len() <= MAX_SIGNERS, SaleError::InvalidSignerSet);
+    require!(
+        threshold > 0 && threshold as usize <= signers.len(),
+        SaleError::InvalidSignerSet
+    );
+    for (i, signer) in signers.iter().enumerate() {
+        require!(
+            *signer != Pubkey::default() && !signers[..i].contains(signer),
+            SaleError::InvalidSignerSet
+        );
+    }
+    Ok(())
+}
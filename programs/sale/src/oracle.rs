@@ -0,0 +1,247 @@
+use anchor_lang::prelude::*;
+
+use crate::vesting::BPS_DENOMINATOR;
+use crate::SaleError;
+
+// Layout of a Pyth v2 price account; only the fields read here are listed
+const MAGIC: u32 = 0xa1b2_c3d4;
+const ACCOUNT_TYPE_PRICE: u32 = 3;
+const STATUS_TRADING: u32 = 1;
+const EXPO_OFFSET: usize = 20;
+const PUBLISH_TIME_OFFSET: usize = 96;
+const AGG_PRICE_OFFSET: usize = 208;
+const AGG_CONF_OFFSET: usize = 216;
+const AGG_STATUS_OFFSET: usize = 224;
+const PRICE_ACCOUNT_MIN_LEN: usize = 240;
+
+// USD prices are stored in micro-dollars
+const USD_DECIMALS: u32 = 6;
+const LAMPORT_DECIMALS: u32 = 9;
+
+// A campaign priced in USD; each purchase converts the price to lamports with
+// a SOL/USD feed owned by `oracle_program`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub struct UsdPricing {
+    // Default pubkey when the campaign is priced in lamports
+    pub price_feed: Pubkey,
+    pub oracle_program: Pubkey, // Copied from ProtocolConfig
+    pub usd_price: u64,         // Micro-dollars per whole token
+    pub max_price_age: i64,     // Seconds a feed update stays usable
+    // Widest confidence interval accepted, relative to the price
+    pub max_confidence_bps: u16,
+}
+
+impl UsdPricing {
+    pub fn is_enabled(&self) -> bool {
+        self.price_feed != Pubkey::default()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.usd_price > 0
+                && self.max_price_age > 0
+                && self.max_confidence_bps > 0
+                && self.max_confidence_bps as u64 <= BPS_DENOMINATOR,
+            SaleError::InvalidUsdPricing
+        );
+        Ok(())
+    }
+}
+
+// SOL/USD aggregate: one SOL is worth `price * 10^expo` dollars, give or
+// take `conf` in the same units
+pub struct OraclePrice {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    bytes.copy_from_slice(&data[offset..offset + N]);
+    bytes
+}
+
+// Rejects accounts that are not price accounts of `oracle_program`
+pub fn check_feed(feed: &AccountInfo, oracle_program: &Pubkey) -> Result<()> {
+    require_keys_eq!(*feed.owner, *oracle_program, SaleError::InvalidPriceFeed);
+    let data = feed.try_borrow_data()?;
+    require!(
+        data.len() >= PRICE_ACCOUNT_MIN_LEN
+            && u32::from_le_bytes(read(&data, 0)) == MAGIC
+            && u32::from_le_bytes(read(&data, 8)) == ACCOUNT_TYPE_PRICE,
+        SaleError::InvalidPriceFeed
+    );
+    Ok(())
+}
+
+// Reads the campaign's feed. Fails closed: a feed that is not trading, is
+// older than `max_price_age` or is too uncertain never yields a price.
+pub fn read_price(feed: &AccountInfo, pricing: &UsdPricing, now: i64) -> Result<OraclePrice> {
+    require_keys_eq!(feed.key(), pricing.price_feed, SaleError::InvalidPriceFeed);
+    check_feed(feed, &pricing.oracle_program)?;
+    let data = feed.try_borrow_data()?;
+    let price = OraclePrice {
+        price: i64::from_le_bytes(read(&data, AGG_PRICE_OFFSET)),
+        conf: u64::from_le_bytes(read(&data, AGG_CONF_OFFSET)),
+        expo: i32::from_le_bytes(read(&data, EXPO_OFFSET)),
+        publish_time: i64::from_le_bytes(read(&data, PUBLISH_TIME_OFFSET)),
+    };
+    require!(
+        u32::from_le_bytes(read(&data, AGG_STATUS_OFFSET)) == STATUS_TRADING
+            && now.saturating_sub(price.publish_time) <= pricing.max_price_age,
+        SaleError::StalePrice
+    );
+    require!(price.price > 0, SaleError::InvalidPriceFeed);
+    require!(
+        price.conf as u128 * BPS_DENOMINATOR as u128
+            <= price.price as u128 * pricing.max_confidence_bps as u128,
+        SaleError::PriceTooUncertain
+    );
+    Ok(price)
+}
+
+// Lamports per whole token for `usd_price` micro-dollars at the feed's SOL
+// price, rounded up so buyers never pay less than the USD price
+pub fn lamports_per_token(usd_price: u64, sol: &OraclePrice) -> Result<u64> {
+    // lamports = usd_price * 10^(9 - 6) / (price * 10^expo)
+    let shift = LAMPORT_DECIMALS as i32 - USD_DECIMALS as i32 - sol.expo;
+    let scale = 10u128
+        .checked_pow(shift.unsigned_abs())
+        .ok_or(SaleError::MathOverflow)?;
+    let (numerator, denominator) = if shift >= 0 {
+        (usd_price as u128)
+            .checked_mul(scale)
+            .zip(Some(sol.price as u128))
+    } else {
+        Some(usd_price as u128).zip((sol.price as u128).checked_mul(scale))
+    }
+    .ok_or(SaleError::MathOverflow)?;
+    let lamports = numerator
+        .checked_add(denominator - 1)
+        .ok_or(SaleError::MathOverflow)?
+        / denominator;
+    require!(lamports > 0, SaleError::InvalidPrice);
+    u64::try_from(lamports).map_err(|_| error!(SaleError::MathOverflow))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_760_000_000;
+
+    fn sol(price: i64, expo: i32) -> OraclePrice {
+        OraclePrice {
+            price,
+            conf: 0,
+            expo,
+            publish_time: NOW,
+        }
+    }
+
+    fn pricing(feed: Pubkey, oracle_program: Pubkey) -> UsdPricing {
+        UsdPricing {
+            price_feed: feed,
+            oracle_program,
+            usd_price: 250_000,
+            max_price_age: 60,
+            max_confidence_bps: 50, // 0.5%
+        }
+    }
+
+    // A trading price account holding `price`/`conf` at expo -8
+    fn feed_data(price: i64, conf: u64, publish_time: i64) -> Vec<u8> {
+        let mut data = vec![0u8; PRICE_ACCOUNT_MIN_LEN];
+        data[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        data[8..12].copy_from_slice(&ACCOUNT_TYPE_PRICE.to_le_bytes());
+        data[EXPO_OFFSET..EXPO_OFFSET + 4].copy_from_slice(&(-8i32).to_le_bytes());
+        data[PUBLISH_TIME_OFFSET..PUBLISH_TIME_OFFSET + 8]
+            .copy_from_slice(&publish_time.to_le_bytes());
+        data[AGG_PRICE_OFFSET..AGG_PRICE_OFFSET + 8].copy_from_slice(&price.to_le_bytes());
+        data[AGG_CONF_OFFSET..AGG_CONF_OFFSET + 8].copy_from_slice(&conf.to_le_bytes());
+        data[AGG_STATUS_OFFSET..AGG_STATUS_OFFSET + 4]
+            .copy_from_slice(&STATUS_TRADING.to_le_bytes());
+        data
+    }
+
+    // Reads `data` as the campaign's feed at NOW
+    fn read(mut data: Vec<u8>) -> Result<OraclePrice> {
+        let (key, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut lamports = 0;
+        let feed = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        read_price(&feed, &pricing(key, owner), NOW)
+    }
+
+    fn code(result: Result<OraclePrice>) -> u32 {
+        match result {
+            Err(Error::AnchorError(err)) => err.error_code_number,
+            _ => panic!("expected a program error"),
+        }
+    }
+
+    #[test]
+    fn quotes_with_a_positive_shift() {
+        // $1.50 at $150/SOL is exactly 0.01 SOL
+        let sol_150 = sol(15_000_000_000, -8);
+        assert_eq!(lamports_per_token(1_500_000, &sol_150).unwrap(), 10_000_000);
+        // $0.25 at $150/SOL is 1_666_666.67 lamports, rounded up
+        assert_eq!(lamports_per_token(250_000, &sol_150).unwrap(), 1_666_667);
+    }
+
+    #[test]
+    fn quotes_with_a_negative_shift() {
+        // 1 * 10^4: SOL at $10,000, so $1 is 0.0001 SOL
+        let sol_10k = sol(1, 4);
+        assert_eq!(lamports_per_token(1_000_000, &sol_10k).unwrap(), 100_000);
+        assert_eq!(lamports_per_token(1_000_001, &sol_10k).unwrap(), 100_001);
+    }
+
+    #[test]
+    fn rejects_quotes_that_overflow() {
+        assert!(lamports_per_token(u64::MAX, &sol(1, -30)).is_err());
+        assert!(lamports_per_token(1, &sol(1, 60)).is_err());
+    }
+
+    #[test]
+    fn accepts_confidence_up_to_the_bound() {
+        // 0.5% of $150 is $0.75
+        let price = read(feed_data(15_000_000_000, 75_000_000, NOW)).unwrap();
+        assert_eq!(price.price, 15_000_000_000);
+        assert_eq!(price.conf, 75_000_000);
+        assert_eq!(
+            code(read(feed_data(15_000_000_000, 75_000_001, NOW))),
+            u32::from(SaleError::PriceTooUncertain)
+        );
+    }
+
+    #[test]
+    fn accepts_prices_up_to_the_max_age() {
+        assert!(read(feed_data(15_000_000_000, 0, NOW - 60)).is_ok());
+        assert_eq!(
+            code(read(feed_data(15_000_000_000, 0, NOW - 61))),
+            u32::from(SaleError::StalePrice)
+        );
+    }
+
+    #[test]
+    fn rejects_feeds_that_are_not_trading_or_not_positive() {
+        let mut halted = feed_data(15_000_000_000, 0, NOW);
+        halted[AGG_STATUS_OFFSET] = 0;
+        assert_eq!(code(read(halted)), u32::from(SaleError::StalePrice));
+        assert_eq!(
+            code(read(feed_data(0, 0, NOW))),
+            u32::from(SaleError::InvalidPriceFeed)
+        );
+    }
+}
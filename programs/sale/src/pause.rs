@@ -0,0 +1,20 @@
+use anchor_lang::prelude::*;
+
+// Longest pause reason stored on the campaign, in bytes
+pub const MAX_PAUSE_REASON: usize = 64;
+
+// Actions a pause can block. `withdrawals` covers the admin side's payouts:
+// withdrawals and unsold-token reclaims. Refunds are never paused, so buyers
+// of a failed or cancelled sale can always get their money back.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub struct PauseFlags {
+    pub donate: bool,
+    pub claims: bool,
+    pub withdrawals: bool,
+}
+
+impl PauseFlags {
+    pub fn any(&self) -> bool {
+        self.donate || self.claims || self.withdrawals
+    }
+}
@@ -0,0 +1,67 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, TransferChecked};
+
+use crate::vesting::BPS_DENOMINATOR;
+use crate::{Campaign, SaleError};
+
+// Lamports the admin side may take out of a lamport campaign: everything above
+// rent except what is set aside for uniform-clearing rebates and referrers
+pub fn withdrawable_lamports(campaign: &Account<Campaign>) -> Result<u64> {
+    let info = campaign.to_account_info();
+    let rent_balance = Rent::get()?.minimum_balance(info.data_len());
+    Ok(info
+        .lamports()
+        .saturating_sub(rent_balance)
+        .saturating_sub(campaign.rebates_owed)
+        .saturating_sub(campaign.referral_payment_owed))
+}
+
+// Payment-vault balance the admin side may take out, net of referral
+// commissions still owed
+pub fn withdrawable_spl(campaign: &Campaign, vault_balance: u64) -> u64 {
+    vault_balance.saturating_sub(campaign.referral_payment_owed)
+}
+
+// Splits a withdrawal into (protocol fee, recipient share). The fee rounds
+// down so the project never pays more than `fee_bps`.
+pub fn split_fee(amount: u64, fee_bps: u16) -> Result<(u64, u64)> {
+    let fee = (amount as u128)
+        .checked_mul(fee_bps as u128)
+        .map(|v| v / BPS_DENOMINATOR as u128)
+        .ok_or(SaleError::MathOverflow)? as u64;
+    Ok((fee, amount - fee))
+}
+
+// Moves lamports out of the program-owned campaign account
+pub fn send_lamports(campaign: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    **campaign.try_borrow_mut_lamports()? -= amount;
+    **to.try_borrow_mut_lamports()? += amount;
+    Ok(())
+}
+
+// Campaign-signed transfer out of one of its vaults
+pub fn send_from_vault<'info>(
+    campaign: &Account<'info, Campaign>,
+    vault: &Account<'info, TokenAccount>,
+    mint: &Account<'info, Mint>,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let cpi_accounts = TransferChecked {
+        from: vault.to_account_info(),
+        mint: mint.to_account_info(),
+        to: to.to_account_info(),
+        authority: campaign.to_account_info(),
+    };
+    campaign.with_signer(|signer| {
+        token::transfer_checked(
+            CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer),
+            amount,
+            mint.decimals,
+        )
+    })
+}
@@ -0,0 +1,171 @@
+use anchor_lang::prelude::*;
+
+use crate::SaleError;
+
+// How the per-token price is set during the sale
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum PricingMode {
+    // `token_price` for the whole sale (or each round's own price)
+    #[default]
+    Fixed,
+    // Price decays linearly from `token_price` at start_ts to `floor_price` at
+    // end_ts, which extend_sale leaves alone. With `uniform_clearing` every
+    // buyer ends up paying the clearing price and the difference is rebated
+    // at claim time.
+    DutchAuction {
+        floor_price: u64,
+        uniform_clearing: bool,
+    },
+}
+
+impl PricingMode {
+    pub fn validate(&self, start_price: u64, has_rounds: bool) -> Result<()> {
+        if let PricingMode::DutchAuction { floor_price, .. } = *self {
+            require!(!has_rounds, SaleError::InvalidPricingMode);
+            require!(
+                floor_price > 0 && floor_price < start_price,
+                SaleError::InvalidPricingMode
+            );
+        }
+        Ok(())
+    }
+}
+
+// Auction price at `now` for a sale running from `start_ts` to `end_ts`
+pub fn dutch_price(
+    start_price: u64,
+    floor_price: u64,
+    start_ts: i64,
+    end_ts: i64,
+    now: i64,
+) -> u64 {
+    let duration = (end_ts - start_ts).max(1) as u128;
+    let elapsed = (now - start_ts).clamp(0, end_ts - start_ts) as u128;
+    let decay = (start_price - floor_price) as u128 * elapsed / duration;
+    start_price - decay as u64
+}
+
+// Payment units paid for `tokens` base units at `token_price` per whole token,
+// rounded down
+pub fn sale_value(tokens: u64, token_price: u64, decimals: u8) -> Result<u128> {
+    (tokens as u128)
+        .checked_mul(token_price as u128)
+        .and_then(|v| v.checked_div(10u128.checked_pow(decimals as u32)?))
+        .ok_or_else(|| error!(SaleError::MathOverflow))
+}
+
+// Tokens (base units) bought with `amount` payment units at `token_price` per
+// whole token. Rounds down so any remainder stays with the campaign.
+pub fn tokens_for_payment(amount: u64, token_price: u64, decimals: u8) -> Result<u64> {
+    let tokens = (amount as u128)
+        .checked_mul(
+            10u128
+                .checked_pow(decimals as u32)
+                .ok_or(SaleError::MathOverflow)?,
+        )
+        .and_then(|v| v.checked_div(token_price as u128))
+        .ok_or(SaleError::MathOverflow)?;
+    u64::try_from(tokens).map_err(|_| error!(SaleError::MathOverflow))
+}
+
+// Payment units owed for `tokens` base units at `token_price` per whole token.
+// Rounds up so the buyer never pays less than the tokens are worth.
+pub fn payment_for_tokens(tokens: u64, token_price: u64, decimals: u8) -> Result<u64> {
+    let scale = 10u128
+        .checked_pow(decimals as u32)
+        .ok_or(SaleError::MathOverflow)?;
+    let payment = (tokens as u128)
+        .checked_mul(token_price as u128)
+        .and_then(|v| v.checked_add(scale - 1))
+        .map(|v| v / scale)
+        .ok_or(SaleError::MathOverflow)?;
+    u64::try_from(payment).map_err(|_| error!(SaleError::MathOverflow))
+}
+
+// Tokens bought with `amount` when `tokens_left` remain, and the payment
+// charged for them. Partial fill: a purchase worth more than is left gets the
+// rest and is charged only for that, rounded up but never above `amount`.
+pub fn fill(amount: u64, token_price: u64, decimals: u8, tokens_left: u64) -> Result<(u64, u64)> {
+    let tokens = tokens_for_payment(amount, token_price, decimals)?;
+    if tokens <= tokens_left {
+        return Ok((tokens, amount));
+    }
+    let charged = payment_for_tokens(tokens_left, token_price, decimals)?.min(amount);
+    Ok((tokens_left, charged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: u64 = 1_000_000_000;
+
+    #[test]
+    fn converts_with_the_token_decimals() {
+        // 0.1 SOL per whole token of a 9-decimal mint
+        assert_eq!(tokens_for_payment(SOL, SOL / 10, 9).unwrap(), 10 * SOL);
+        assert_eq!(payment_for_tokens(10 * SOL, SOL / 10, 9).unwrap(), SOL);
+        // 0.1 units of a 6-decimal payment mint per whole 0-decimal token
+        assert_eq!(tokens_for_payment(250_000, 100_000, 0).unwrap(), 2);
+        assert_eq!(payment_for_tokens(2, 100_000, 0).unwrap(), 200_000);
+    }
+
+    #[test]
+    fn rounds_against_the_buyer() {
+        // Tokens round down, the remainder stays with the campaign
+        assert_eq!(tokens_for_payment(10, 3, 0).unwrap(), 3);
+        assert_eq!(tokens_for_payment(1, 3, 9).unwrap(), 333_333_333);
+        assert_eq!(tokens_for_payment(2, 3, 0).unwrap(), 0);
+        // Payments round up, so a fraction of a lamport costs a whole one
+        assert_eq!(payment_for_tokens(333_333_333, 3, 9).unwrap(), 1);
+        assert_eq!(payment_for_tokens(1, 3, 9).unwrap(), 1);
+        assert_eq!(payment_for_tokens(0, 3, 9).unwrap(), 0);
+        assert_eq!(sale_value(333_333_333, 3, 9).unwrap(), 0);
+    }
+
+    #[test]
+    fn buying_back_what_a_payment_bought_never_costs_more() {
+        for amount in [1, 7, 999, SOL - 1, SOL, 3 * SOL + 1] {
+            for price in [1, 3, 7_777, SOL / 10, 3 * SOL] {
+                let tokens = tokens_for_payment(amount, price, 9).unwrap();
+                assert!(payment_for_tokens(tokens, price, 9).unwrap() <= amount);
+            }
+        }
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        // More base units than a u64 can hold
+        assert!(tokens_for_payment(u64::MAX, 1, 9).is_err());
+        assert!(payment_for_tokens(u64::MAX, u64::MAX, 0).is_err());
+        // 10^39 does not fit in a u128
+        assert!(tokens_for_payment(1, 1, 39).is_err());
+        assert!(payment_for_tokens(1, 1, 39).is_err());
+        assert!(sale_value(u64::MAX, u64::MAX, 39).is_err());
+        // A zero price cannot be divided by
+        assert!(tokens_for_payment(SOL, 0, 9).is_err());
+    }
+
+    #[test]
+    fn fills_whole_purchases_while_tokens_last() {
+        assert_eq!(fill(SOL, SOL / 10, 9, 100 * SOL).unwrap(), (10 * SOL, SOL));
+        // Exactly what is left is still a whole purchase
+        assert_eq!(fill(SOL, SOL / 10, 9, 10 * SOL).unwrap(), (10 * SOL, SOL));
+        // A whole purchase keeps the remainder too small for another unit
+        assert_eq!(fill(10, 3, 0, 5).unwrap(), (3, 10));
+        // Too little for a single unit, which record rejects
+        assert_eq!(fill(2, 3, 0, 5).unwrap(), (0, 2));
+    }
+
+    #[test]
+    fn partially_fills_the_last_purchase() {
+        // Half a SOL offered for the last 3 tokens pays 0.3 SOL
+        assert_eq!(
+            fill(SOL / 2, SOL / 10, 9, 3 * SOL).unwrap(),
+            (3 * SOL, 3 * SOL / 10)
+        );
+        // The charge rounds up to a whole payment unit
+        assert_eq!(fill(10, 3, 0, 2).unwrap(), (2, 6));
+        assert_eq!(fill(SOL, 3, 9, 1).unwrap(), (1, 1));
+    }
+}
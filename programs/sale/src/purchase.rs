@@ -0,0 +1,180 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    merkle, pricing, referral, status, BuyerPosition, Campaign, CampaignStatus, ReferrerStats,
+    SaleError, TokensPurchased,
+};
+
+// Public purchases are only allowed while no whitelist applies
+pub fn check_public(campaign: &Campaign, now: i64) -> Result<()> {
+    require!(
+        campaign.whitelist_root_at(now)? == [0u8; 32],
+        SaleError::WhitelistRequired
+    );
+    Ok(())
+}
+
+// Verifies the buyer's Merkle proof and, when the leaf carries one, that the
+// purchase stays within their allocation
+pub fn check_whitelisted(
+    campaign: &Campaign,
+    position: &BuyerPosition,
+    buyer: &Pubkey,
+    amount: u64,
+    allocation: u64,
+    proof: &[[u8; 32]],
+    now: i64,
+) -> Result<()> {
+    let root = campaign.whitelist_root_at(now)?;
+    let leaf = merkle::leaf(buyer, allocation);
+    require!(merkle::verify(proof, root, leaf), SaleError::NotWhitelisted);
+    if allocation > 0 {
+        let contributed = position
+            .contributed
+            .checked_add(amount)
+            .ok_or(SaleError::MathOverflow)?;
+        require!(contributed <= allocation, SaleError::AllocationExceeded);
+    }
+    Ok(())
+}
+
+// Checks a purchase of up to `amount` payment units against the sale rules and
+// books it on the campaign and the buyer's position. Returns the amount actually
+// charged, which the caller must move: a purchase larger than the tokens or
+// hard cap left is filled with what remains and charged only for that. A
+// referrer, when given, is credited with their commission. `quote` replaces
+// the campaign's own price outside of rounds, for USD-priced sales.
+pub fn record(
+    campaign: &mut Account<Campaign>,
+    position: &mut Account<BuyerPosition>,
+    referrer_stats: Option<&mut Account<ReferrerStats>>,
+    buyer: Pubkey,
+    position_bump: u8,
+    amount: u64,
+    quote: Option<u64>,
+) -> Result<u64> {
+    require!(campaign.status.is_open(), SaleError::SaleNotOpen);
+    require!(!campaign.paused.donate, SaleError::ActionPaused);
+    let now = Clock::get()?.unix_timestamp;
+    require!(now >= campaign.start_ts, SaleError::SaleNotStarted);
+    require!(now < campaign.end_ts, SaleError::SaleEnded);
+    if campaign.status == CampaignStatus::Scheduled {
+        // First purchase after start_ts activates the sale
+        status::transition(campaign, CampaignStatus::Active, now)?;
+    }
+
+    let mut tokens_left = campaign
+        .total_tokens
+        .checked_sub(campaign.tokens_sold)
+        .ok_or(SaleError::MathOverflow)?;
+    require!(tokens_left > 0, SaleError::SaleEnded);
+
+    // Multi-round campaigns price and cap each purchase by the open round
+    let round = campaign.active_round(now)?;
+    let token_price = match round {
+        Some(index) => {
+            tokens_left = tokens_left.min(campaign.round_tokens_left(index));
+            require!(tokens_left > 0, SaleError::RoundSoldOut);
+            campaign.rounds[index].config.token_price
+        }
+        None => quote.unwrap_or_else(|| campaign.current_price(now)),
+    };
+    campaign.last_price = token_price;
+
+    // The hard cap limits the raise the same way the supply limits the tokens
+    let cap_left = campaign
+        .hard_cap
+        .checked_sub(campaign.amount_donated)
+        .ok_or(SaleError::MathOverflow)?;
+    let tokens_in_cap =
+        pricing::tokens_for_payment(cap_left, token_price, campaign.token_decimals)?;
+    require!(tokens_in_cap > 0, SaleError::HardCapReached);
+    tokens_left = tokens_left.min(tokens_in_cap);
+
+    // Rounding up a partial fill's charge never takes it past the hard cap,
+    // since `tokens_left` was rounded down from it
+    let (tokens_to_buy, charged) =
+        pricing::fill(amount, token_price, campaign.token_decimals, tokens_left)?;
+    require!(tokens_to_buy > 0, SaleError::PurchaseTooSmall);
+
+    // Limits apply to the buyer's running total, not to each purchase. The
+    // minimum is judged on what the buyer offered so a partial fill that
+    // sells out the sale is not rejected for being small.
+    let offered = position
+        .contributed
+        .checked_add(amount)
+        .ok_or(SaleError::MathOverflow)?;
+    require!(
+        offered >= campaign.min_contribution,
+        SaleError::BelowMinContribution
+    );
+    let contributed = position
+        .contributed
+        .checked_add(charged)
+        .ok_or(SaleError::MathOverflow)?;
+    require!(
+        campaign.max_contribution_per_wallet == 0
+            || contributed <= campaign.max_contribution_per_wallet,
+        SaleError::AboveMaxContribution
+    );
+
+    if position.buyer == Pubkey::default() {
+        // First purchase from this buyer in this campaign
+        position.campaign = campaign.key();
+        position.buyer = buyer;
+        position.first_purchase_ts = now;
+        position.bump = position_bump;
+        campaign.open_positions = campaign
+            .open_positions
+            .checked_add(1)
+            .ok_or(SaleError::MathOverflow)?;
+    }
+    position.contributed = contributed;
+    position.tokens_bought = position
+        .tokens_bought
+        .checked_add(tokens_to_buy)
+        .ok_or(SaleError::MathOverflow)?;
+    position.last_purchase_ts = now;
+
+    campaign.tokens_sold = campaign
+        .tokens_sold
+        .checked_add(tokens_to_buy)
+        .ok_or(SaleError::MathOverflow)?;
+    campaign.amount_donated = campaign
+        .amount_donated
+        .checked_add(charged)
+        .ok_or(SaleError::MathOverflow)?;
+    if let Some(index) = round {
+        let round = &mut campaign.rounds[index];
+        round.tokens_sold = round
+            .tokens_sold
+            .checked_add(tokens_to_buy)
+            .ok_or(SaleError::MathOverflow)?;
+    }
+
+    // Sold out once every token is gone or the hard cap has no room for more
+    if campaign.tokens_sold == campaign.total_tokens || tokens_to_buy == tokens_in_cap {
+        status::transition(campaign, CampaignStatus::SoldOut, now)?;
+    }
+
+    let mut referrer = Pubkey::default();
+    if let Some(stats) = referrer_stats {
+        referral::credit(campaign, stats, buyer, charged, tokens_to_buy, now)?;
+        referrer = stats.referrer;
+    }
+
+    emit!(TokensPurchased {
+        campaign: campaign.key(),
+        buyer,
+        referrer,
+        amount_paid: charged,
+        tokens_bought: tokens_to_buy,
+        token_price,
+        buyer_contributed: position.contributed,
+        buyer_tokens: position.tokens_bought,
+        tokens_sold: campaign.tokens_sold,
+        amount_donated: campaign.amount_donated,
+        timestamp: now,
+    });
+    Ok(charged)
+}
@@ -0,0 +1,108 @@
+use anchor_lang::prelude::*;
+
+use crate::vesting::BPS_DENOMINATOR;
+use crate::{Campaign, ReferralCredited, ReferrerStats, SaleError};
+
+// Highest referral commission a campaign can offer, in basis points
+pub const MAX_REFERRAL_BPS: u16 = 2_000;
+
+// What a referrer earns on each referred purchase
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum ReferralReward {
+    // A share of the payment, in lamports or payment-mint base units
+    #[default]
+    Payment,
+    // A share of the tokens bought, paid from a budget deposited on top of
+    // the sale supply
+    BonusTokens,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub struct ReferralConfig {
+    pub commission_bps: u16, // 0 when the campaign pays no commission
+    pub reward: ReferralReward,
+    pub token_budget: u64, // Bonus tokens on offer, BonusTokens only
+}
+
+impl ReferralConfig {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.commission_bps <= MAX_REFERRAL_BPS,
+            SaleError::InvalidReferralConfig
+        );
+        require!(
+            self.reward == ReferralReward::BonusTokens || self.token_budget == 0,
+            SaleError::InvalidReferralConfig
+        );
+        Ok(())
+    }
+}
+
+// Credits `stats` for a purchase of `tokens` that cost `charged` payment units.
+// Bonus tokens stop once the campaign's budget runs out; the purchase itself
+// still goes through.
+pub fn credit(
+    campaign: &mut Account<Campaign>,
+    stats: &mut Account<ReferrerStats>,
+    buyer: Pubkey,
+    charged: u64,
+    tokens: u64,
+    now: i64,
+) -> Result<()> {
+    require_keys_neq!(stats.referrer, buyer, SaleError::InvalidReferrer);
+    require_keys_neq!(stats.referrer, campaign.admin, SaleError::InvalidReferrer);
+
+    let config = campaign.referral;
+    let base = match config.reward {
+        ReferralReward::Payment => charged,
+        ReferralReward::BonusTokens => tokens,
+    };
+    let mut reward =
+        (base as u128 * config.commission_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    match config.reward {
+        ReferralReward::Payment => {
+            campaign.referral_payment_owed = campaign
+                .referral_payment_owed
+                .checked_add(reward)
+                .ok_or(SaleError::MathOverflow)?;
+        }
+        ReferralReward::BonusTokens => {
+            reward = reward.min(
+                config
+                    .token_budget
+                    .saturating_sub(campaign.referral_tokens_credited),
+            );
+            campaign.referral_tokens_credited = campaign
+                .referral_tokens_credited
+                .checked_add(reward)
+                .ok_or(SaleError::MathOverflow)?;
+            campaign.referral_tokens_owed = campaign
+                .referral_tokens_owed
+                .checked_add(reward)
+                .ok_or(SaleError::MathOverflow)?;
+        }
+    }
+
+    stats.referred_purchases = stats
+        .referred_purchases
+        .checked_add(1)
+        .ok_or(SaleError::MathOverflow)?;
+    stats.referred_volume = stats
+        .referred_volume
+        .checked_add(charged)
+        .ok_or(SaleError::MathOverflow)?;
+    stats.rewards_earned = stats
+        .rewards_earned
+        .checked_add(reward)
+        .ok_or(SaleError::MathOverflow)?;
+
+    emit!(ReferralCredited {
+        campaign: campaign.key(),
+        referrer: stats.referrer,
+        buyer,
+        volume: charged,
+        reward,
+        timestamp: now,
+    });
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use anchor_lang::prelude::*;
+
+use crate::SaleError;
+
+pub const MAX_ROUNDS: usize = 4;
+
+// Admin-supplied terms of one pricing round (seed, private, public, ...)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct RoundConfig {
+    pub token_price: u64,      // Payment units per whole token in this round
+    pub token_allocation: u64, // Tokens offered in this round, in base units
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub whitelist_root: [u8; 32], // Zeroes when the round is public
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct SaleRound {
+    pub config: RoundConfig,
+    pub tokens_sold: u64,
+}
+
+// What happens to tokens a round did not sell once it ends
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum UnsoldPolicy {
+    #[default]
+    RollForward, // Offered again in the next round
+    ReturnToAdmin, // Held back and reclaimable by the admin after finalize
+}
+
+// Rounds must be ordered, non-overlapping, inside the sale window and
+// together offer exactly `total_tokens`
+pub fn validate_rounds(
+    rounds: &[RoundConfig],
+    start_ts: i64,
+    end_ts: i64,
+    total_tokens: u64,
+) -> Result<()> {
+    require!(rounds.len() <= MAX_ROUNDS, SaleError::InvalidRounds);
+
+    let mut allocated: u64 = 0;
+    let mut previous_end = start_ts;
+    for round in rounds {
+        require!(round.token_price > 0, SaleError::InvalidPrice);
+        require!(round.token_allocation > 0, SaleError::InvalidRounds);
+        require!(
+            round.start_ts >= previous_end && round.start_ts < round.end_ts,
+            SaleError::InvalidRounds
+        );
+        allocated = allocated
+            .checked_add(round.token_allocation)
+            .ok_or(SaleError::MathOverflow)?;
+        previous_end = round.end_ts;
+    }
+    if !rounds.is_empty() {
+        require!(
+            previous_end <= end_ts && allocated == total_tokens,
+            SaleError::InvalidRounds
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use anchor_lang::prelude::*;
+
+use crate::{Campaign, CampaignStatusChanged, SaleError};
+
+// Lifecycle phase of a campaign. Time alone never moves a campaign between
+// phases; every change goes through `transition` from an instruction.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, InitSpace)]
+pub enum CampaignStatus {
+    #[default]
+    Draft, // Created, vault not yet proven funded
+    Scheduled, // Opened, waiting for start_ts
+    Active,    // Taking purchases
+    SoldOut,   // Every token sold or hard cap reached, waiting for finalize
+    Succeeded, // Finalized at or above target
+    Failed,    // Finalized below target, buyers refunded
+    Cancelled, // Called off by the admin before finalize, buyers refunded
+    Claiming,  // Succeeded and buyers may claim their tokens
+}
+
+impl CampaignStatus {
+    // Transitions the program allows; anything else is rejected
+    pub fn can_transition_to(self, next: CampaignStatus) -> bool {
+        use CampaignStatus::*;
+        matches!(
+            (self, next),
+            (Draft, Scheduled | Active | Cancelled)
+                | (Scheduled, Active | Succeeded | Failed | Cancelled)
+                | (Active, SoldOut | Succeeded | Failed | Cancelled)
+                | (SoldOut, Succeeded | Failed | Cancelled)
+                | (Succeeded, Claiming)
+        )
+    }
+
+    // Purchases are possible (subject to the sale window)
+    pub fn is_open(self) -> bool {
+        matches!(self, CampaignStatus::Scheduled | CampaignStatus::Active)
+    }
+
+    // The outcome is decided and the sale terms are frozen
+    pub fn is_settled(self) -> bool {
+        matches!(
+            self,
+            CampaignStatus::Succeeded
+                | CampaignStatus::Failed
+                | CampaignStatus::Cancelled
+                | CampaignStatus::Claiming
+        )
+    }
+
+    // Raised funds belong to the admin
+    pub fn is_successful(self) -> bool {
+        matches!(self, CampaignStatus::Succeeded | CampaignStatus::Claiming)
+    }
+
+    // Raised funds go back to the buyers
+    pub fn is_refundable(self) -> bool {
+        matches!(self, CampaignStatus::Failed | CampaignStatus::Cancelled)
+    }
+}
+
+// Moves the campaign to `next` if the lifecycle allows it
+pub fn transition(campaign: &mut Account<Campaign>, next: CampaignStatus, now: i64) -> Result<()> {
+    let previous = campaign.status;
+    require!(
+        previous.can_transition_to(next),
+        SaleError::InvalidStatusTransition
+    );
+    campaign.status = next;
+
+    emit!(CampaignStatusChanged {
+        campaign: campaign.key(),
+        previous,
+        status: next,
+        timestamp: now,
+    });
+    Ok(())
+}
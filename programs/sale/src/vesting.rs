@@ -0,0 +1,153 @@
+use anchor_lang::prelude::*;
+
+use crate::SaleError;
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+// Release schedule applied to every buyer's tokens, measured from the
+// moment claiming is enabled (TGE)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct VestingConfig {
+    pub tge_unlock_bps: u16,   // Share unlocked at TGE, in basis points
+    pub cliff_seconds: i64,    // Nothing beyond the TGE share unlocks before this
+    pub vesting_duration: i64, // Linear release length after the cliff
+    pub release_interval: i64, // Step size of the linear release, 0 for continuous
+}
+
+impl VestingConfig {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.tge_unlock_bps as u64 <= BPS_DENOMINATOR,
+            SaleError::InvalidVestingConfig
+        );
+        require!(
+            self.cliff_seconds >= 0 && self.vesting_duration >= 0 && self.release_interval >= 0,
+            SaleError::InvalidVestingConfig
+        );
+        require!(
+            self.release_interval <= self.vesting_duration,
+            SaleError::InvalidVestingConfig
+        );
+        Ok(())
+    }
+
+    // Tokens out of `total` that have unlocked by `now` for a TGE at `tge_ts`
+    pub fn vested_amount(&self, total: u64, tge_ts: i64, now: i64) -> Result<u64> {
+        if now < tge_ts {
+            return Ok(0);
+        }
+        let tge_amount =
+            (total as u128 * self.tge_unlock_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        let elapsed = now - tge_ts;
+        if elapsed < self.cliff_seconds {
+            return Ok(tge_amount);
+        }
+        if self.vesting_duration == 0 {
+            return Ok(total);
+        }
+
+        let mut vesting_elapsed = (elapsed - self.cliff_seconds).min(self.vesting_duration);
+        if self.release_interval > 0 && vesting_elapsed < self.vesting_duration {
+            // Only whole intervals count until the schedule completes
+            vesting_elapsed -= vesting_elapsed % self.release_interval;
+        }
+
+        let linear = ((total - tge_amount) as u128)
+            .checked_mul(vesting_elapsed as u128)
+            .ok_or(SaleError::MathOverflow)?
+            / self.vesting_duration as u128;
+        Ok(tge_amount + linear as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400;
+    const TGE: i64 = 1_700_000_000;
+    const TOTAL: u64 = 1_000_000;
+
+    // 20% at TGE, a 30-day cliff, then monthly steps over 180 days
+    fn schedule() -> VestingConfig {
+        VestingConfig {
+            tge_unlock_bps: 2_000,
+            cliff_seconds: 30 * DAY,
+            vesting_duration: 180 * DAY,
+            release_interval: 30 * DAY,
+        }
+    }
+
+    fn vested(config: &VestingConfig, now: i64) -> u64 {
+        config.vested_amount(TOTAL, TGE, now).unwrap()
+    }
+
+    #[test]
+    fn nothing_before_tge() {
+        assert_eq!(vested(&schedule(), TGE - 1), 0);
+    }
+
+    #[test]
+    fn tge_share_until_the_cliff_ends() {
+        let config = schedule();
+        assert_eq!(vested(&config, TGE), 200_000);
+        assert_eq!(vested(&config, TGE + 30 * DAY - 1), 200_000);
+        assert_eq!(vested(&config, TGE + 30 * DAY), 200_000);
+    }
+
+    #[test]
+    fn releases_whole_intervals_only() {
+        let config = schedule();
+        let cliff_end = TGE + 30 * DAY;
+        assert_eq!(vested(&config, cliff_end + 30 * DAY - 1), 200_000);
+        // One of six intervals: 800_000 / 6, rounded down
+        assert_eq!(vested(&config, cliff_end + 30 * DAY), 333_333);
+        assert_eq!(vested(&config, cliff_end + 45 * DAY), 333_333);
+        assert_eq!(vested(&config, cliff_end + 150 * DAY), 866_666);
+    }
+
+    #[test]
+    fn everything_once_the_schedule_completes() {
+        let config = schedule();
+        let cliff_end = TGE + 30 * DAY;
+        assert_eq!(vested(&config, cliff_end + 180 * DAY - 1), 866_666);
+        assert_eq!(vested(&config, cliff_end + 180 * DAY), TOTAL);
+        assert_eq!(vested(&config, cliff_end + 400 * DAY), TOTAL);
+    }
+
+    #[test]
+    fn continuous_release_without_an_interval() {
+        let config = VestingConfig {
+            release_interval: 0,
+            ..schedule()
+        };
+        assert_eq!(vested(&config, TGE + 30 * DAY + 90 * DAY), 600_000);
+    }
+
+    #[test]
+    fn no_duration_unlocks_everything_at_the_cliff() {
+        let config = VestingConfig {
+            vesting_duration: 0,
+            release_interval: 0,
+            ..schedule()
+        };
+        assert_eq!(vested(&config, TGE + 30 * DAY - 1), 200_000);
+        assert_eq!(vested(&config, TGE + 30 * DAY), TOTAL);
+        assert_eq!(vested(&VestingConfig::default(), TGE), TOTAL);
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        let too_much_at_tge = VestingConfig {
+            tge_unlock_bps: 10_001,
+            ..schedule()
+        };
+        assert!(too_much_at_tge.validate().is_err());
+        let interval_past_duration = VestingConfig {
+            release_interval: 181 * DAY,
+            ..schedule()
+        };
+        assert!(interval_past_duration.validate().is_err());
+        assert!(schedule().validate().is_ok());
+    }
+}
@@ -1,16 +1,1949 @@
 import * as anchor from "@coral-xyz/anchor";
-import { Program } from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { Keypair, LAMPORTS_PER_SOL, PublicKey } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { keccak_256 } from "@noble/hashes/sha3";
+import { assert } from "chai";
 import { Sale } from "../target/types/sale";
 
 describe("sale", () => {
   // Configure the client to use the local cluster.
-  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
 
   const program = anchor.workspace.Sale as Program<Sale>;
+  const admin = provider.wallet.publicKey;
+  const payer = (provider.wallet as anchor.Wallet).payer;
 
-  it("Is initialized!", async () => {
-    // Add your test here.
-    const tx = await program.methods.initialize().rpc();
-    console.log("Your transaction signature", tx);
+  // Campaign PDAs are keyed by creator and the creator's campaign index
+  const campaignPda = (creator: PublicKey, index = 0) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("CROWDFUND"),
+        creator.toBuffer(),
+        new BN(index).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  const campaign = campaignPda(admin);
+
+  const totalTokens = new BN(100_000_000_000); // 100 tokens at 9 decimals
+  const protocolFeeBps = 250; // 2.5%
+  const feeRecipient = Keypair.generate().publicKey;
+  let tokenMint: PublicKey;
+  let tokenVault: PublicKey;
+
+  before(async () => {
+    tokenMint = await createMint(provider.connection, payer, admin, null, 9);
+    tokenVault = getAssociatedTokenAddressSync(tokenMint, campaign, true);
+
+    // Campaigns copy their fee from the protocol config, so it must exist first
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    await program.methods
+      .initProtocolConfig(protocolFeeBps, feeRecipient)
+      .accounts({ admin, programData })
+      .rpc();
+    await program.methods.initRegistry().accounts({ user: admin }).rpc();
+  });
+
+  // A fresh keypair with SOL for rent, so each extra campaign gets its own PDA
+  const fundedKeypair = async () => {
+    const keypair = Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(
+        keypair.publicKey,
+        2 * LAMPORTS_PER_SOL
+      )
+    );
+    return keypair;
+  };
+
+  const campaignArgs = () => {
+    const now = Math.floor(Date.now() / 1000);
+    return {
+      targetAmount: new BN(5_000_000_000), // 5 SOL
+      hardCap: new BN(10_000_000_000), // 10 SOL
+      totalTokens,
+      tokenPrice: new BN(100_000_000), // 0.1 SOL per token
+      tokenDecimals: 9,
+      startTs: new BN(now - 60), // already started, whatever the clock skew
+      endTs: new BN(now + 3600),
+      vesting: {
+        tgeUnlockBps: 2_000, // 20% at TGE
+        cliffSeconds: new BN(30 * 86_400),
+        vestingDuration: new BN(180 * 86_400),
+        releaseInterval: new BN(30 * 86_400),
+      },
+      whitelistRoot: Array(32).fill(0), // public sale
+      minContribution: new BN(10_000_000), // 0.01 SOL
+      maxContributionPerWallet: new BN(2_000_000_000), // 2 SOL
+      rounds: [], // single fixed-price sale
+      unsoldPolicy: { rollForward: {} },
+      pricingMode: { fixed: {} },
+    };
+  };
+
+  type TestCampaign = {
+    creator: Keypair;
+    campaign: PublicKey;
+    tokenVault: PublicKey;
+  };
+
+  // First campaign of a fresh wallet, built from `campaignArgs()` with
+  // `overrides` applied
+  const createCampaign = async (overrides = {}): Promise<TestCampaign> => {
+    const creator = await fundedKeypair();
+    await program.methods
+      .initRegistry()
+      .accounts({ user: creator.publicKey })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .create({ ...campaignArgs(), ...overrides })
+      .accounts({ user: creator.publicKey, tokenMint })
+      .signers([creator])
+      .rpc();
+    const campaign = campaignPda(creator.publicKey);
+    return {
+      creator,
+      campaign,
+      tokenVault: getAssociatedTokenAddressSync(tokenMint, campaign, true),
+    };
+  };
+
+  // Mints `amount` sale tokens to the creator, deposits them and opens the sale
+  const openCampaign = async (sale: TestCampaign, amount = totalTokens) => {
+    const creatorTokens = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      tokenMint,
+      sale.creator.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      tokenMint,
+      creatorTokens.address,
+      admin,
+      BigInt(amount.toString())
+    );
+    await program.methods
+      .depositTokens(amount)
+      .accountsPartial({
+        campaign: sale.campaign,
+        admin: sale.creator.publicKey,
+        tokenMint,
+        adminTokenAccount: creatorTokens.address,
+        tokenVault: sale.tokenVault,
+      })
+      .signers([sale.creator])
+      .rpc();
+    await program.methods
+      .openSale()
+      .accountsPartial({
+        campaign: sale.campaign,
+        admin: sale.creator.publicKey,
+        tokenVault: sale.tokenVault,
+      })
+      .signers([sale.creator])
+      .rpc();
+  };
+
+  // Lamport purchase of `amount` by `buyer`, optionally credited to a referrer
+  const donate = (
+    campaign: PublicKey,
+    buyer: Keypair,
+    amount: BN,
+    referrerStats: PublicKey | null = null
+  ) =>
+    program.methods
+      .donate(amount)
+      .accounts({
+        campaign,
+        referrerStats,
+        priceFeed: null,
+        user: buyer.publicKey,
+      })
+      .signers([buyer])
+      .rpc();
+
+  // campaignArgs' amounts restated for a 6-decimal payment mint
+  const splTerms = {
+    targetAmount: new BN(5_000_000), // 5 units
+    hardCap: new BN(10_000_000), // 10 units
+    tokenPrice: new BN(100_000), // 0.1 units per token
+    floorPrice: new BN(0), // fixed-price sale
+    roundPrices: [] as BN[], // no rounds
+    minContribution: new BN(10_000), // 0.01 units
+    maxContributionPerWallet: new BN(2_000_000), // 2 units
+  };
+
+  // The validator's clock, which sale windows are checked against
+  const chainTime = async () =>
+    (await provider.connection.getBlockTime(
+      await provider.connection.getSlot()
+    )) ?? 0;
+
+  // Polls until the validator's clock has passed `ts`
+  const waitUntil = async (ts: number) => {
+    while ((await chainTime()) <= ts) {
+      await new Promise((resolve) => setTimeout(resolve, 500));
+    }
+  };
+
+  // A 10-token sale worth 1 SOL that one buyer can sell out
+  const smallSale = {
+    totalTokens: new BN(10_000_000_000),
+    targetAmount: new BN(500_000_000), // 0.5 SOL
+    hardCap: new BN(1_000_000_000), // 1 SOL
+    maxContributionPerWallet: new BN(0),
+  };
+
+  const positionPda = (campaign: PublicKey, buyer: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("POSITION"), campaign.toBuffer(), buyer.toBuffer()],
+      program.programId
+    )[0];
+
+  // Whitelist hashing, mirroring programs/sale/src/merkle.rs
+  const whitelistLeaf = (buyer: PublicKey, allocation: BN) =>
+    Buffer.from(
+      keccak_256(
+        Buffer.concat([
+          buyer.toBuffer(),
+          allocation.toArrayLike(Buffer, "le", 8),
+        ])
+      )
+    );
+  const hashPair = (a: Buffer, b: Buffer) =>
+    Buffer.from(
+      keccak_256(
+        Buffer.compare(a, b) <= 0
+          ? Buffer.concat([a, b])
+          : Buffer.concat([b, a])
+      )
+    );
+
+  it("Creates a campaign from the supplied args", async () => {
+    const args = campaignArgs();
+
+    await program.methods
+      .create(args)
+      .accounts({ user: admin, tokenMint })
+      .rpc();
+
+    const account = await program.account.campaign.fetch(campaign);
+    assert.ok(account.admin.equals(admin));
+    assert.ok(account.tokenMint.equals(tokenMint));
+    assert.ok(account.tokenVault.equals(tokenVault));
+    assert.ok(account.targetAmount.eq(args.targetAmount));
+    assert.ok(account.hardCap.eq(args.hardCap));
+    assert.ok(account.totalTokens.eq(args.totalTokens));
+    assert.ok(account.tokenPrice.eq(args.tokenPrice));
+    assert.equal(account.tokenDecimals, 9);
+    assert.ok(account.tokensSold.eqn(0));
+    assert.equal(account.vesting.tgeUnlockBps, 2_000);
+    assert.ok(account.vesting.vestingDuration.eq(args.vesting.vestingDuration));
+    assert.deepEqual(account.status, { draft: {} });
+    assert.ok(account.treasury.equals(admin));
+    assert.equal(account.protocolFeeBps, protocolFeeBps);
+  });
+
+  it("Refuses to open the sale before the vault is funded", async () => {
+    try {
+      await program.methods
+        .openSale()
+        .accounts({ campaign, admin, tokenVault })
+        .rpc();
+      assert.fail("open_sale should fail on an empty vault");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "VaultUnderfunded");
+    }
+  });
+
+  it("Opens the sale after the admin deposits the tokens", async () => {
+    const adminTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      tokenMint,
+      admin
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      tokenMint,
+      adminTokenAccount.address,
+      admin,
+      BigInt(totalTokens.toString())
+    );
+
+    await program.methods
+      .depositTokens(totalTokens)
+      .accounts({
+        campaign,
+        admin,
+        tokenMint,
+        adminTokenAccount: adminTokenAccount.address,
+        tokenVault,
+      })
+      .rpc();
+    await program.methods
+      .openSale()
+      .accounts({ campaign, admin, tokenVault })
+      .rpc();
+
+    const vault = await getAccount(provider.connection, tokenVault);
+    assert.equal(vault.amount.toString(), totalTokens.toString());
+    const account = await program.account.campaign.fetch(campaign);
+    assert.deepEqual(account.status, { active: {} });
+  });
+
+  it("Extends the sale within the allowed bound", async () => {
+    const before = await program.account.campaign.fetch(campaign);
+    const newEnd = before.endTs.addn(3600);
+    await program.methods
+      .extendSale(newEnd)
+      .accounts({ campaign, admin })
+      .rpc();
+
+    const after = await program.account.campaign.fetch(campaign);
+    assert.ok(after.endTs.eq(newEnd));
+    assert.ok(after.totalExtension.eqn(3600));
+
+    try {
+      await program.methods
+        .extendSale(newEnd.addn(31 * 86_400))
+        .accounts({ campaign, admin })
+        .rpc();
+      assert.fail("extend_sale should reject extensions past the limit");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidExtension");
+    }
+  });
+
+  it("Hands the campaign to a new admin in two steps", async () => {
+    const newAdmin = Keypair.generate();
+
+    await program.methods
+      .proposeAdmin(newAdmin.publicKey)
+      .accounts({ campaign, admin })
+      .rpc();
+    let account = await program.account.campaign.fetch(campaign);
+    assert.ok(account.pendingAdmin.equals(newAdmin.publicKey));
+    assert.ok(account.admin.equals(admin));
+
+    await program.methods
+      .acceptAdmin()
+      .accounts({ campaign, newAdmin: newAdmin.publicKey })
+      .signers([newAdmin])
+      .rpc();
+    account = await program.account.campaign.fetch(campaign);
+    assert.ok(account.admin.equals(newAdmin.publicKey));
+    assert.ok(account.pendingAdmin.equals(PublicKey.default));
+    assert.ok(account.creator.equals(admin));
+
+    // Hand it back so the remaining tests keep using the provider wallet
+    await program.methods
+      .proposeAdmin(admin)
+      .accounts({ campaign, admin: newAdmin.publicKey })
+      .signers([newAdmin])
+      .rpc();
+    await program.methods
+      .acceptAdmin()
+      .accounts({ campaign, newAdmin: admin })
+      .rpc();
+    account = await program.account.campaign.fetch(campaign);
+    assert.ok(account.admin.equals(admin));
+  });
+
+  it("Lets the guardian pause and unpause selected actions", async () => {
+    const guardian = Keypair.generate();
+    await program.methods
+      .setGuardian(guardian.publicKey)
+      .accounts({ campaign, admin })
+      .rpc();
+
+    const actions = { donate: true, claims: false, withdrawals: true };
+    await program.methods
+      .pause(actions, "pricing mistake in round 1")
+      .accounts({ campaign, authority: guardian.publicKey })
+      .signers([guardian])
+      .rpc();
+    let account = await program.account.campaign.fetch(campaign);
+    assert.deepEqual(account.paused, actions);
+    assert.equal(account.pauseReason, "pricing mistake in round 1");
+    assert.ok(account.pausedAt.gtn(0));
+
+    const stranger = Keypair.generate();
+    try {
+      await program.methods
+        .unpause()
+        .accounts({ campaign, authority: stranger.publicKey })
+        .signers([stranger])
+        .rpc();
+      assert.fail("unpause should reject keys other than admin and guardian");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "Unauthorized");
+    }
+
+    await program.methods
+      .unpause()
+      .accounts({ campaign, authority: admin })
+      .rpc();
+    account = await program.account.campaign.fetch(campaign);
+    assert.deepEqual(account.paused, {
+      donate: false,
+      claims: false,
+      withdrawals: false,
+    });
+    assert.equal(account.pauseReason, "");
+  });
+
+  it("Registers referrers but never the campaign admin", async () => {
+    const referrer = await fundedKeypair();
+    const [referrerStats] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("REFERRER"),
+        campaign.toBuffer(),
+        referrer.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .registerReferrer()
+      .accounts({ campaign, referrer: referrer.publicKey })
+      .signers([referrer])
+      .rpc();
+    const stats = await program.account.referrerStats.fetch(referrerStats);
+    assert.ok(stats.referrer.equals(referrer.publicKey));
+    assert.ok(stats.rewardsEarned.eqn(0));
+
+    try {
+      await program.methods
+        .registerReferrer()
+        .accounts({ campaign, referrer: admin })
+        .rpc();
+      assert.fail("the admin should not be able to register as a referrer");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidReferrer");
+    }
+  });
+
+  it("Takes payments in a configured SPL mint", async () => {
+    // A second admin keeps this campaign separate from the lamport one
+    const { creator: splAdmin, campaign: splCampaign } = await createCampaign();
+    const paymentMint = await createMint(
+      provider.connection,
+      payer,
+      admin,
+      null,
+      6
+    );
+    const configure = (terms: typeof splTerms) =>
+      program.methods
+        .configureSplPayment(terms)
+        .accounts({
+          campaign: splCampaign,
+          admin: splAdmin.publicKey,
+          paymentMint,
+        })
+        .signers([splAdmin])
+        .rpc();
+
+    // A hard cap left in lamports is far beyond what the tokens sell for
+    try {
+      await configure({ ...splTerms, hardCap: new BN(10_000_000_000) });
+      assert.fail("terms are validated like a new campaign");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidHardCap");
+    }
+    await configure(splTerms);
+
+    const account = await program.account.campaign.fetch(splCampaign);
+    assert.ok(account.paymentMint.equals(paymentMint));
+    assert.ok(
+      account.paymentVault.equals(
+        getAssociatedTokenAddressSync(paymentMint, splCampaign, true)
+      )
+    );
+    assert.equal(account.paymentDecimals, 6);
+    assert.ok(account.tokenPrice.eq(splTerms.tokenPrice));
+    assert.ok(account.targetAmount.eq(splTerms.targetAmount));
+    assert.ok(account.hardCap.eq(splTerms.hardCap));
+  });
+
+  it("Cancels an unfinalized campaign and refuses to reopen it", async () => {
+    const {
+      creator: cancelAdmin,
+      campaign: cancelCampaign,
+      tokenVault: cancelVault,
+    } = await createCampaign();
+
+    await program.methods
+      .cancelCampaign()
+      .accounts({ campaign: cancelCampaign, admin: cancelAdmin.publicKey })
+      .signers([cancelAdmin])
+      .rpc();
+
+    const account = await program.account.campaign.fetch(cancelCampaign);
+    assert.deepEqual(account.status, { cancelled: {} });
+
+    try {
+      await program.methods
+        .openSale()
+        .accounts({
+          campaign: cancelCampaign,
+          admin: cancelAdmin.publicKey,
+          tokenVault: cancelVault,
+        })
+        .signers([cancelAdmin])
+        .rpc();
+      assert.fail("open_sale should reject a cancelled campaign");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "SaleAlreadyOpened");
+    }
+
+    // Nothing was sold or deposited, so the rent can come straight back
+    await program.methods
+      .closeCampaign()
+      .accounts({
+        campaign: cancelCampaign,
+        admin: cancelAdmin.publicKey,
+        tokenVault: cancelVault,
+        paymentVault: null,
+      })
+      .signers([cancelAdmin])
+      .rpc();
+    assert.isNull(
+      await program.account.campaign.fetchNullable(cancelCampaign)
+    );
+  });
+
+  it("Requires a valid signer set for multisig withdrawals", async () => {
+    const { creator: msAdmin, campaign: msCampaign } = await createCampaign();
+    const signers = [
+      Keypair.generate().publicKey,
+      Keypair.generate().publicKey,
+    ];
+
+    try {
+      await program.methods
+        .setWithdrawalSigners(signers, 3)
+        .accounts({ campaign: msCampaign, admin: msAdmin.publicKey })
+        .signers([msAdmin])
+        .rpc();
+      assert.fail("threshold above the signer count should be rejected");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidSignerSet");
+    }
+
+    await program.methods
+      .setWithdrawalSigners(signers, 2)
+      .accounts({ campaign: msCampaign, admin: msAdmin.publicKey })
+      .signers([msAdmin])
+      .rpc();
+    const account = await program.account.campaign.fetch(msCampaign);
+    assert.equal(account.threshold, 2);
+    assert.equal(account.signers.length, 2);
+    assert.ok(account.signers[0].equals(signers[0]));
+  });
+
+  it("Creates several campaigns from one wallet", async () => {
+    const launchpad = await fundedKeypair();
+    const [registry] = PublicKey.findProgramAddressSync(
+      [Buffer.from("REGISTRY"), launchpad.publicKey.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .initRegistry()
+      .accounts({ user: launchpad.publicKey })
+      .signers([launchpad])
+      .rpc();
+    for (let i = 0; i < 2; i++) {
+      await program.methods
+        .create(campaignArgs())
+        .accounts({ user: launchpad.publicKey, tokenMint })
+        .signers([launchpad])
+        .rpc();
+    }
+
+    const account = await program.account.adminRegistry.fetch(registry);
+    assert.ok(account.nextIndex.eqn(2));
+    assert.deepEqual(
+      account.campaigns.map((key) => key.toBase58()),
+      [0, 1].map((i) => campaignPda(launchpad.publicKey, i).toBase58())
+    );
+    const second = await program.account.campaign.fetch(
+      campaignPda(launchpad.publicKey, 1)
+    );
+    assert.ok(second.index.eqn(1));
+  });
+
+  it("Prices tokens in USD and refuses stale oracle prices", async () => {
+    // Mock SOL/USD feed from tests/fixtures: $150.00, published 2025-10-09
+    const oracleProgram = new PublicKey(
+      "FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi9epH"
+    );
+    const priceFeed = new PublicKey(
+      "H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG"
+    );
+    const usdPrice = new BN(250_000); // $0.25 per token
+    const [registry] = PublicKey.findProgramAddressSync(
+      [Buffer.from("REGISTRY"), admin.toBuffer()],
+      program.programId
+    );
+    const adminTokenAccount = getAssociatedTokenAddressSync(tokenMint, admin);
+
+    await program.methods
+      .setOracleProgram(oracleProgram)
+      .accounts({ admin })
+      .rpc();
+
+    // Creates, prices and opens a USD campaign from the admin wallet
+    const openUsdCampaign = async (maxPriceAge: number) => {
+      const { nextIndex } = await program.account.adminRegistry.fetch(registry);
+      const usdCampaign = campaignPda(admin, nextIndex.toNumber());
+      const usdVault = getAssociatedTokenAddressSync(
+        tokenMint,
+        usdCampaign,
+        true
+      );
+      await program.methods
+        .create(campaignArgs())
+        .accounts({ user: admin, tokenMint })
+        .rpc();
+      await program.methods
+        .configureUsdPricing(usdPrice, new BN(maxPriceAge), 100)
+        .accounts({ campaign: usdCampaign, admin, priceFeed })
+        .rpc();
+      await mintTo(
+        provider.connection,
+        payer,
+        tokenMint,
+        adminTokenAccount,
+        admin,
+        BigInt(totalTokens.toString())
+      );
+      await program.methods
+        .depositTokens(totalTokens)
+        .accounts({
+          campaign: usdCampaign,
+          admin,
+          tokenMint,
+          adminTokenAccount,
+          tokenVault: usdVault,
+        })
+        .rpc();
+      await program.methods
+        .openSale()
+        .accounts({ campaign: usdCampaign, admin, tokenVault: usdVault })
+        .rpc();
+      return usdCampaign;
+    };
+
+    const buyer = await fundedKeypair();
+    const amount = new BN(100_000_000); // 0.1 SOL
+    const donateUsd = (usdCampaign: PublicKey) =>
+      program.methods
+        .donate(amount)
+        .accounts({
+          campaign: usdCampaign,
+          referrerStats: null,
+          priceFeed,
+          user: buyer.publicKey,
+        })
+        .signers([buyer])
+        .rpc();
+
+    // A feed this old is only accepted by a very lenient campaign
+    const usdCampaign = await openUsdCampaign(10 * 365 * 86_400);
+    const configured = await program.account.campaign.fetch(usdCampaign);
+    assert.ok(configured.usdPricing.priceFeed.equals(priceFeed));
+    assert.ok(configured.usdPricing.oracleProgram.equals(oracleProgram));
+    assert.ok(configured.usdPricing.usdPrice.eq(usdPrice));
+
+    await donateUsd(usdCampaign);
+    // $0.25 at $150/SOL, rounded up to the lamport
+    const quote = new BN(1_666_667);
+    const account = await program.account.campaign.fetch(usdCampaign);
+    assert.ok(account.lastPrice.eq(quote));
+    // The lamport price the target and hard cap were checked against stays
+    assert.ok(account.tokenPrice.eq(campaignArgs().tokenPrice));
+    const bought = await program.account.buyerPosition.fetch(
+      positionPda(usdCampaign, buyer.publicKey)
+    );
+    assert.ok(bought.contributed.eq(amount));
+    assert.ok(
+      bought.tokensBought.eq(amount.mul(new BN(1_000_000_000)).div(quote))
+    );
+
+    const strictCampaign = await openUsdCampaign(60);
+    try {
+      await donateUsd(strictCampaign);
+      assert.fail("donate should fail closed on a stale price");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "StalePrice");
+    }
+  });
+
+  it("Admits whitelisted buyers up to their Merkle allocation", async () => {
+    const buyer = await fundedKeypair();
+    const other = await fundedKeypair();
+    const allocation = new BN(50_000_000); // 0.05 SOL
+    const buyerLeaf = whitelistLeaf(buyer.publicKey, allocation);
+    const otherLeaf = whitelistLeaf(other.publicKey, new BN(0));
+    const root = hashPair(buyerLeaf, otherLeaf);
+    const sale = await createCampaign({ whitelistRoot: Array.from(root) });
+    await openCampaign(sale);
+
+    const donateWhitelisted = (amount: BN, claimed: BN, proof: Buffer[]) =>
+      program.methods
+        .donateWhitelisted(
+          amount,
+          claimed,
+          proof.map((node) => Array.from(node))
+        )
+        .accounts({
+          campaign: sale.campaign,
+          referrerStats: null,
+          priceFeed: null,
+          user: buyer.publicKey,
+        })
+        .signers([buyer])
+        .rpc();
+
+    try {
+      await donate(sale.campaign, buyer, new BN(30_000_000));
+      assert.fail("a whitelisted sale should refuse public purchases");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "WhitelistRequired");
+    }
+    try {
+      // Claiming a larger allocation than the leaf commits to
+      await donateWhitelisted(new BN(30_000_000), new BN(0), [otherLeaf]);
+      assert.fail("a proof for the wrong leaf should be rejected");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "NotWhitelisted");
+    }
+
+    await donateWhitelisted(new BN(30_000_000), allocation, [otherLeaf]);
+    const position = await program.account.buyerPosition.fetch(
+      positionPda(sale.campaign, buyer.publicKey)
+    );
+    assert.ok(position.contributed.eqn(30_000_000));
+
+    try {
+      await donateWhitelisted(new BN(30_000_000), allocation, [otherLeaf]);
+      assert.fail("purchases past the allocation should be rejected");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "AllocationExceeded");
+    }
+  });
+
+  it("Stops the sale at its hard cap with a partial fill", async () => {
+    const sale = await createCampaign({
+      targetAmount: new BN(500_000_000), // 0.5 SOL
+      hardCap: new BN(1_000_000_000), // 1 SOL, a tenth of the supply's value
+      maxContributionPerWallet: new BN(0),
+    });
+    await openCampaign(sale);
+    const first = await fundedKeypair();
+    const last = await fundedKeypair();
+
+    await donate(sale.campaign, first, new BN(600_000_000));
+    // Only 0.4 SOL of room is left under the cap
+    await donate(sale.campaign, last, new BN(600_000_000));
+
+    const position = await program.account.buyerPosition.fetch(
+      positionPda(sale.campaign, last.publicKey)
+    );
+    assert.ok(position.contributed.eqn(400_000_000));
+    assert.ok(position.tokensBought.eqn(4_000_000_000)); // 4 tokens
+    const account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.amountDonated.eq(account.hardCap));
+    assert.deepEqual(account.status, { soldOut: {} });
+
+    try {
+      await donate(sale.campaign, first, new BN(100_000_000));
+      assert.fail("purchases should stop once the hard cap is reached");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "SaleNotOpen");
+    }
+  });
+
+  it("Creates a campaign whose vault was opened in advance", async () => {
+    const creator = await fundedKeypair();
+    const campaign = campaignPda(creator.publicKey);
+    // Anyone can open the ATA of the predictable campaign address first
+    await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      tokenMint,
+      campaign,
+      true
+    );
+
+    await program.methods
+      .initRegistry()
+      .accounts({ user: creator.publicKey })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .create(campaignArgs())
+      .accounts({ user: creator.publicKey, tokenMint })
+      .signers([creator])
+      .rpc();
+
+    const account = await program.account.campaign.fetch(campaign);
+    assert.ok(
+      account.tokenVault.equals(
+        getAssociatedTokenAddressSync(tokenMint, campaign, true)
+      )
+    );
+  });
+
+  it("Delivers only the vested share on claim", async () => {
+    const sale = await createCampaign(smallSale);
+    await openCampaign(sale, smallSale.totalTokens);
+    const buyer = await fundedKeypair();
+    await donate(sale.campaign, buyer, new BN(1_000_000_000));
+
+    // Selling out lets the sale settle before its end
+    await program.methods
+      .finalize()
+      .accounts({ campaign: sale.campaign })
+      .rpc();
+    let account = await program.account.campaign.fetch(sale.campaign);
+    assert.deepEqual(account.status, { succeeded: {} });
+
+    const claim = () =>
+      program.methods
+        .claim()
+        .accounts({ campaign: sale.campaign, buyer: buyer.publicKey })
+        .signers([buyer])
+        .rpc();
+    try {
+      await claim();
+      assert.fail("claims should wait for enable_claim");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "ClaimNotEnabled");
+    }
+
+    await program.methods
+      .enableClaim()
+      .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+      .signers([sale.creator])
+      .rpc();
+    await claim();
+
+    // 20% unlocks at TGE, the rest waits for the 30-day cliff
+    const tgeShare = smallSale.totalTokens.divn(5);
+    const buyerTokens = await getAccount(
+      provider.connection,
+      getAssociatedTokenAddressSync(tokenMint, buyer.publicKey)
+    );
+    assert.equal(buyerTokens.amount.toString(), tgeShare.toString());
+    const position = await program.account.buyerPosition.fetch(
+      positionPda(sale.campaign, buyer.publicKey)
+    );
+    assert.ok(position.tokensClaimed.eq(tgeShare));
+    account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.tokensClaimed.eq(tgeShare));
+
+    try {
+      await claim();
+      assert.fail("a second claim before more vests should send nothing");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "NothingToClaim");
+    }
+  });
+
+  it("Fails a sale that missed its target and refunds its buyers", async () => {
+    const endTs = (await chainTime()) + 20;
+    const sale = await createCampaign({ ...smallSale, endTs: new BN(endTs) });
+    await openCampaign(sale, smallSale.totalTokens);
+    const buyer = await fundedKeypair();
+    const amount = new BN(100_000_000); // 0.1 SOL, short of the 0.5 SOL target
+    await donate(sale.campaign, buyer, amount);
+
+    const finalize = () =>
+      program.methods.finalize().accounts({ campaign: sale.campaign }).rpc();
+    try {
+      await finalize();
+      assert.fail("finalize should wait for the sale to end");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "SaleStillActive");
+    }
+    await waitUntil(endTs);
+    await finalize();
+    const account = await program.account.campaign.fetch(sale.campaign);
+    assert.deepEqual(account.status, { failed: {} });
+
+    const refund = () =>
+      program.methods
+        .refund()
+        .accounts({ campaign: sale.campaign, buyer: buyer.publicKey })
+        .signers([buyer])
+        .rpc();
+    const before = await provider.connection.getBalance(buyer.publicKey);
+    await refund();
+    const after = await provider.connection.getBalance(buyer.publicKey);
+    // The provider wallet pays the fee, so the whole contribution comes back
+    assert.equal(after - before, amount.toNumber());
+    const refunded = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(refunded.amountRefunded.eq(amount));
+
+    try {
+      await refund();
+      assert.fail("a position can only be refunded once");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "NothingToRefund");
+    }
+  });
+
+  it("Refunds payment-mint buyers of a cancelled sale", async () => {
+    const sale = await createCampaign();
+    const paymentMint = await createMint(
+      provider.connection,
+      payer,
+      admin,
+      null,
+      6
+    );
+    await program.methods
+      .configureSplPayment(splTerms)
+      .accounts({
+        campaign: sale.campaign,
+        admin: sale.creator.publicKey,
+        paymentMint,
+      })
+      .signers([sale.creator])
+      .rpc();
+    await openCampaign(sale);
+
+    const buyer = await fundedKeypair();
+    const buyerPayment = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      paymentMint,
+      buyer.publicKey
+    );
+    const amount = new BN(200_000); // 2 tokens at 0.1 units
+    await mintTo(
+      provider.connection,
+      payer,
+      paymentMint,
+      buyerPayment.address,
+      admin,
+      BigInt(amount.toString())
+    );
+    const paymentVault = getAssociatedTokenAddressSync(
+      paymentMint,
+      sale.campaign,
+      true
+    );
+    await program.methods
+      .donateSpl(amount)
+      .accountsPartial({
+        campaign: sale.campaign,
+        referrerStats: null,
+        user: buyer.publicKey,
+        paymentMint,
+        userPaymentAccount: buyerPayment.address,
+        paymentVault,
+      })
+      .signers([buyer])
+      .rpc();
+    let balance = await getAccount(provider.connection, buyerPayment.address);
+    assert.equal(balance.amount.toString(), "0");
+
+    await program.methods
+      .cancelCampaign()
+      .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+      .signers([sale.creator])
+      .rpc();
+    await program.methods
+      .refundSpl()
+      .accountsPartial({
+        campaign: sale.campaign,
+        buyer: buyer.publicKey,
+        paymentMint,
+        paymentVault,
+        buyerPaymentAccount: buyerPayment.address,
+      })
+      .signers([buyer])
+      .rpc();
+
+    balance = await getAccount(provider.connection, buyerPayment.address);
+    assert.equal(balance.amount.toString(), amount.toString());
+    const vault = await getAccount(provider.connection, paymentVault);
+    assert.equal(vault.amount.toString(), "0");
+  });
+
+  it("Sells out a whitelisted payment-mint sale and withdraws it", async () => {
+    const buyer = await fundedKeypair();
+    const allocation = new BN(1_000_000); // the whole hard cap
+    const buyerLeaf = whitelistLeaf(buyer.publicKey, allocation);
+    const otherLeaf = whitelistLeaf(Keypair.generate().publicKey, new BN(0));
+    const sale = await createCampaign({
+      ...smallSale,
+      whitelistRoot: Array.from(hashPair(buyerLeaf, otherLeaf)),
+    });
+    const paymentMint = await createMint(
+      provider.connection,
+      payer,
+      admin,
+      null,
+      6
+    );
+    // smallSale restated: 10 tokens at 0.1 units sell out at 1 unit
+    await program.methods
+      .configureSplPayment({
+        ...splTerms,
+        targetAmount: new BN(500_000),
+        hardCap: allocation,
+        maxContributionPerWallet: new BN(0),
+      })
+      .accounts({
+        campaign: sale.campaign,
+        admin: sale.creator.publicKey,
+        paymentMint,
+      })
+      .signers([sale.creator])
+      .rpc();
+    await openCampaign(sale, smallSale.totalTokens);
+
+    const buyerPayment = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      paymentMint,
+      buyer.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      paymentMint,
+      buyerPayment.address,
+      admin,
+      BigInt(allocation.toString())
+    );
+    const paymentVault = getAssociatedTokenAddressSync(
+      paymentMint,
+      sale.campaign,
+      true
+    );
+    await program.methods
+      .donateSplWhitelisted(allocation, allocation, [Array.from(otherLeaf)])
+      .accountsPartial({
+        campaign: sale.campaign,
+        referrerStats: null,
+        user: buyer.publicKey,
+        paymentMint,
+        userPaymentAccount: buyerPayment.address,
+        paymentVault,
+      })
+      .signers([buyer])
+      .rpc();
+    await program.methods
+      .finalize()
+      .accounts({ campaign: sale.campaign })
+      .rpc();
+    const account = await program.account.campaign.fetch(sale.campaign);
+    assert.deepEqual(account.status, { succeeded: {} });
+
+    // The creator is the campaign's treasury until it sets another
+    const treasuryPayment = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      paymentMint,
+      sale.creator.publicKey
+    );
+    const feePayment = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      paymentMint,
+      feeRecipient
+    );
+    await program.methods
+      .withdrawSpl(allocation)
+      .accountsPartial({
+        campaign: sale.campaign,
+        admin: sale.creator.publicKey,
+        paymentMint,
+        paymentVault,
+        treasuryPaymentAccount: treasuryPayment.address,
+        feePaymentAccount: feePayment.address,
+      })
+      .signers([sale.creator])
+      .rpc();
+
+    const fee = allocation.muln(protocolFeeBps).divn(10_000);
+    const treasury = await getAccount(
+      provider.connection,
+      treasuryPayment.address
+    );
+    assert.equal(treasury.amount.toString(), allocation.sub(fee).toString());
+    const fees = await getAccount(provider.connection, feePayment.address);
+    assert.equal(fees.amount.toString(), fee.toString());
+    const vault = await getAccount(provider.connection, paymentVault);
+    assert.equal(vault.amount.toString(), "0");
+  });
+
+  it("Holds each wallet between the contribution limits", async () => {
+    const sale = await createCampaign({
+      minContribution: new BN(LAMPORTS_PER_SOL / 2),
+      maxContributionPerWallet: new BN(LAMPORTS_PER_SOL),
+    });
+    await openCampaign(sale);
+    const buyer = await fundedKeypair();
+    const sol = (amount: number) => new BN(amount * LAMPORTS_PER_SOL);
+
+    try {
+      await donate(sale.campaign, buyer, sol(0.2));
+      assert.fail("a first purchase must reach the minimum");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "BelowMinContribution");
+    }
+    await donate(sale.campaign, buyer, sol(0.6));
+    // The minimum applies to the running total, so a small top-up is fine
+    await donate(sale.campaign, buyer, sol(0.2));
+    try {
+      await donate(sale.campaign, buyer, sol(0.5));
+      assert.fail("the running total may not pass the maximum");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "AboveMaxContribution");
+    }
+
+    const position = await program.account.buyerPosition.fetch(
+      positionPda(sale.campaign, buyer.publicKey)
+    );
+    assert.ok(position.contributed.eq(sol(0.8)));
+    assert.ok(position.tokensBought.eq(new BN(8e9)));
+
+    // With no per-wallet cap the minimum must still be reachable
+    try {
+      await createCampaign({
+        ...smallSale,
+        minContribution: smallSale.hardCap.addn(1),
+      });
+      assert.fail("a minimum above the hard cap could never be met");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidContributionLimits");
+    }
+  });
+
+  it("Prices each round and rolls unsold tokens forward", async () => {
+    const now = await chainTime();
+    const secondRound = now + 15;
+    const sol = (amount: number) => new BN(amount * LAMPORTS_PER_SOL);
+    const round = (price: BN, tokens: number, start: number, end: number) => ({
+      tokenPrice: price,
+      tokenAllocation: new BN(tokens * 1e9),
+      startTs: new BN(start),
+      endTs: new BN(end),
+      whitelistRoot: Array(32).fill(0),
+    });
+    const sale = await createCampaign({
+      ...smallSale,
+      hardCap: sol(0.8), // every token sold at its round's price
+      startTs: new BN(now - 60),
+      endTs: new BN(now + 3600),
+      rounds: [
+        round(sol(0.05), 4, now - 60, secondRound),
+        round(sol(0.1), 6, secondRound, now + 3600),
+      ],
+    });
+    await openCampaign(sale, smallSale.totalTokens);
+
+    // Round one sells two of its four tokens at 0.05 SOL
+    const early = await fundedKeypair();
+    await donate(sale.campaign, early, sol(0.1));
+    let account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.lastPrice.eq(sol(0.05)));
+    assert.ok(account.rounds[0].tokensSold.eq(new BN(2e9)));
+
+    // Round two offers its own six tokens plus the two left over
+    await waitUntil(secondRound);
+    const late = await fundedKeypair();
+    await donate(sale.campaign, late, sol(0.7));
+    account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.lastPrice.eq(sol(0.1)));
+    assert.ok(account.rounds[1].tokensSold.eq(new BN(7e9)));
+    const position = await program.account.buyerPosition.fetch(
+      positionPda(sale.campaign, late.publicKey)
+    );
+    assert.ok(position.tokensBought.eq(new BN(7e9)));
+  });
+
+  // A uniform-clearing auction falling from 0.2 SOL to a 0.1 SOL floor over
+  // a window that ends 20 seconds from now
+  const floorPrice = new BN(100_000_000);
+  const createAuction = async (targetAmount: BN) => {
+    const now = await chainTime();
+    const sale = await createCampaign({
+      ...smallSale,
+      targetAmount,
+      tokenPrice: new BN(200_000_000),
+      startTs: new BN(now - 60),
+      endTs: new BN(now + 20),
+      pricingMode: { dutchAuction: { floorPrice, uniformClearing: true } },
+    });
+    await openCampaign(sale, smallSale.totalTokens);
+    return { ...sale, endTs: now + 20 };
+  };
+
+  // Lamports owed for `tokens` base units, rounded up like pricing.rs
+  const costAt = (tokens: BN, price: BN) =>
+    tokens
+      .mul(price)
+      .add(new BN(LAMPORTS_PER_SOL - 1))
+      .div(new BN(LAMPORTS_PER_SOL));
+
+  it("Clears an unsold auction at the floor and rebates the rest", async () => {
+    const sale = await createAuction(new BN(300_000_000));
+    const buyer = await fundedKeypair();
+    const paid = new BN(500_000_000);
+    await donate(sale.campaign, buyer, paid);
+
+    // Three quarters of the window have passed, so the price has decayed
+    let account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.lastPrice.lt(new BN(200_000_000)));
+    assert.ok(account.lastPrice.gt(floorPrice));
+    assert.ok(
+      account.tokensSold.eq(
+        paid.mul(new BN(LAMPORTS_PER_SOL)).div(account.lastPrice)
+      )
+    );
+
+    await waitUntil(sale.endTs);
+    await program.methods
+      .finalize()
+      .accounts({ campaign: sale.campaign })
+      .rpc();
+    account = await program.account.campaign.fetch(sale.campaign);
+    assert.deepEqual(account.status, { succeeded: {} });
+    assert.ok(account.clearingPrice.eq(floorPrice));
+    const rebate = paid.sub(costAt(account.tokensSold, floorPrice));
+    assert.ok(account.rebatesOwed.eq(rebate));
+
+    await program.methods
+      .enableClaim()
+      .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+      .signers([sale.creator])
+      .rpc();
+    await program.methods
+      .claim()
+      .accounts({ campaign: sale.campaign, buyer: buyer.publicKey })
+      .signers([buyer])
+      .rpc();
+    const position = await program.account.buyerPosition.fetch(
+      positionPda(sale.campaign, buyer.publicKey)
+    );
+    assert.isTrue(position.rebatePaid);
+    account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.rebatesOwed.isZero());
+  });
+
+  it("Judges an auction's target on what it keeps after rebates", async () => {
+    // 0.5 SOL paid meets the target, but only about 0.4 SOL is kept
+    const sale = await createAuction(new BN(450_000_000));
+    const buyer = await fundedKeypair();
+    await donate(sale.campaign, buyer, new BN(500_000_000));
+
+    try {
+      await program.methods
+        .extendSale(new BN(sale.endTs + 3600))
+        .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+        .signers([sale.creator])
+        .rpc();
+      assert.fail("an auction's price schedule should not move");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "AuctionNotExtendable");
+    }
+
+    await waitUntil(sale.endTs);
+    await program.methods
+      .finalize()
+      .accounts({ campaign: sale.campaign })
+      .rpc();
+    const account = await program.account.campaign.fetch(sale.campaign);
+    assert.deepEqual(account.status, { failed: {} });
+    assert.ok(account.rebatesOwed.isZero());
+  });
+
+  it("Sells the last tokens to an oversized purchase", async () => {
+    const sale = await createCampaign(smallSale);
+    await openCampaign(sale, smallSale.totalTokens);
+    await donate(sale.campaign, await fundedKeypair(), new BN(700_000_000));
+
+    // 0.5 SOL offered for the last 3 tokens buys them for 0.3 SOL
+    const last = await fundedKeypair();
+    await donate(sale.campaign, last, new BN(500_000_000));
+    const position = await program.account.buyerPosition.fetch(
+      positionPda(sale.campaign, last.publicKey)
+    );
+    assert.ok(position.tokensBought.eq(new BN(3e9)));
+    assert.ok(position.contributed.eq(new BN(300_000_000)));
+    const account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.tokensSold.eq(smallSale.totalTokens));
+    assert.ok(account.amountDonated.eq(new BN(LAMPORTS_PER_SOL)));
+    assert.deepEqual(account.status, { soldOut: {} });
+  });
+
+  it("Activates only an opened sale whose start has passed", async () => {
+    const startTs = (await chainTime()) + 10;
+    const sale = await createCampaign({
+      startTs: new BN(startTs),
+      endTs: new BN(startTs + 3600),
+    });
+    const activate = () =>
+      program.methods
+        .activateSale()
+        .accounts({ campaign: sale.campaign })
+        .rpc();
+
+    try {
+      await activate();
+      assert.fail("a draft campaign has to be opened first");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "SaleNotOpen");
+    }
+
+    await openCampaign(sale);
+    let account = await program.account.campaign.fetch(sale.campaign);
+    assert.deepEqual(account.status, { scheduled: {} });
+    try {
+      await activate();
+      assert.fail("activation should wait for start_ts");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "SaleNotStarted");
+    }
+
+    await waitUntil(startTs);
+    await activate();
+    account = await program.account.campaign.fetch(sale.campaign);
+    assert.deepEqual(account.status, { active: {} });
+  });
+
+  it("Counts withdrawal approvals only from current signers", async () => {
+    const sale = await createCampaign();
+    const [first, second, third] = [
+      await fundedKeypair(),
+      await fundedKeypair(),
+      await fundedKeypair(),
+    ];
+    const setSigners = (signers: Keypair[]) =>
+      program.methods
+        .setWithdrawalSigners(signers.map((signer) => signer.publicKey), 2)
+        .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+        .signers([sale.creator])
+        .rpc();
+    const proposal = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("WITHDRAWAL"),
+        sale.campaign.toBuffer(),
+        new BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+    const approve = (signer: Keypair) =>
+      program.methods
+        .approveWithdrawal()
+        .accountsPartial({
+          campaign: sale.campaign,
+          proposal,
+          signer: signer.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+
+    await setSigners([first, second]);
+    await program.methods
+      .proposeWithdrawal(
+        new BN(LAMPORTS_PER_SOL),
+        sale.creator.publicKey,
+        new BN((await chainTime()) + 3600)
+      )
+      .accountsPartial({
+        campaign: sale.campaign,
+        proposal,
+        proposer: first.publicKey,
+      })
+      .signers([first])
+      .rpc();
+
+    // The proposer leaves the set before anyone else approves
+    await setSigners([second, third]);
+    await approve(second);
+    let account = await program.account.withdrawalProposal.fetch(proposal);
+    assert.deepEqual(
+      account.approvals.map((key) => key.toBase58()),
+      [second.publicKey.toBase58()]
+    );
+    await approve(third);
+    account = await program.account.withdrawalProposal.fetch(proposal);
+    assert.equal(account.approvals.length, 2);
+  });
+
+  it("Pays out a funded campaign only through approved proposals", async () => {
+    const sale = await createCampaign(smallSale);
+    const [first, second] = [await fundedKeypair(), await fundedKeypair()];
+    await program.methods
+      .setWithdrawalSigners([first.publicKey, second.publicKey], 2)
+      .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+      .signers([sale.creator])
+      .rpc();
+    await openCampaign(sale, smallSale.totalTokens);
+    const buyer = await fundedKeypair();
+    await donate(sale.campaign, buyer, new BN(LAMPORTS_PER_SOL));
+    await program.methods
+      .finalize()
+      .accounts({ campaign: sale.campaign })
+      .rpc();
+
+    try {
+      await program.methods
+        .withdraw(new BN(LAMPORTS_PER_SOL))
+        .accountsPartial({
+          campaign: sale.campaign,
+          user: sale.creator.publicKey,
+          treasury: sale.creator.publicKey,
+          feeRecipient,
+        })
+        .signers([sale.creator])
+        .rpc();
+      assert.fail("withdraw should defer to the signer set");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "MultisigRequired");
+    }
+
+    const proposalPda = (index: number) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("WITHDRAWAL"),
+          sale.campaign.toBuffer(),
+          new BN(index).toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      )[0];
+    const recipient = Keypair.generate().publicKey;
+    const propose = (index: number, amount: BN, expiresAt: number) =>
+      program.methods
+        .proposeWithdrawal(amount, recipient, new BN(expiresAt))
+        .accountsPartial({
+          campaign: sale.campaign,
+          proposal: proposalPda(index),
+          proposer: first.publicKey,
+        })
+        .signers([first])
+        .rpc();
+    const execute = (index: number) =>
+      program.methods
+        .executeWithdrawal()
+        .accountsPartial({
+          campaign: sale.campaign,
+          proposal: proposalPda(index),
+          recipient,
+          feeRecipient,
+        })
+        .rpc();
+
+    const amount = new BN(400_000_000);
+    await propose(0, amount, (await chainTime()) + 3600);
+    try {
+      await execute(0);
+      assert.fail("one approval is below the threshold of two");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "ThresholdNotMet");
+    }
+
+    await program.methods
+      .approveWithdrawal()
+      .accountsPartial({
+        campaign: sale.campaign,
+        proposal: proposalPda(0),
+        signer: second.publicKey,
+      })
+      .signers([second])
+      .rpc();
+    const feeBefore = await provider.connection.getBalance(feeRecipient);
+    await execute(0);
+    const fee = amount.muln(protocolFeeBps).divn(10_000);
+    assert.equal(
+      await provider.connection.getBalance(recipient),
+      amount.sub(fee).toNumber()
+    );
+    assert.equal(
+      (await provider.connection.getBalance(feeRecipient)) - feeBefore,
+      fee.toNumber()
+    );
+    const executed = await program.account.withdrawalProposal.fetch(
+      proposalPda(0)
+    );
+    assert.isTrue(executed.executed);
+
+    // A proposal that outlives its deadline can no longer be executed
+    const expiresAt = (await chainTime()) + 2;
+    await propose(1, new BN(100_000_000), expiresAt);
+    await waitUntil(expiresAt);
+    try {
+      await execute(1);
+      assert.fail("the proposal has expired");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "ProposalExpired");
+    }
+  });
+
+  it("Takes the protocol fee out of each withdrawal", async () => {
+    const sale = await createCampaign(smallSale);
+    await openCampaign(sale, smallSale.totalTokens);
+    const raised = new BN(LAMPORTS_PER_SOL);
+    await donate(sale.campaign, await fundedKeypair(), raised);
+    await program.methods
+      .finalize()
+      .accounts({ campaign: sale.campaign })
+      .rpc();
+
+    const treasury = Keypair.generate().publicKey;
+    await program.methods
+      .setTreasury(treasury)
+      .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+      .signers([sale.creator])
+      .rpc();
+    const withdraw = (amount: BN) =>
+      program.methods
+        .withdraw(amount)
+        .accountsPartial({
+          campaign: sale.campaign,
+          user: sale.creator.publicKey,
+          treasury,
+          feeRecipient,
+        })
+        .signers([sale.creator])
+        .rpc();
+
+    try {
+      await withdraw(raised.addn(1));
+      assert.fail("only the raised lamports can be withdrawn");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InsufficientVaultBalance");
+    }
+    const feesBefore = await provider.connection.getBalance(feeRecipient);
+    await withdraw(raised);
+
+    const fee = raised.muln(protocolFeeBps).divn(10_000);
+    assert.equal(
+      await provider.connection.getBalance(treasury),
+      raised.sub(fee).toNumber()
+    );
+    assert.equal(
+      (await provider.connection.getBalance(feeRecipient)) - feesBefore,
+      fee.toNumber()
+    );
+    const account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.amountWithdrawn.eq(raised));
+    assert.ok(account.protocolFeesPaid.eq(fee));
+  });
+
+  it("Credits referrers and pays their commission after the sale", async () => {
+    const sale = await createCampaign(smallSale);
+    await program.methods
+      .configureReferrals({
+        commissionBps: 500, // 5%
+        reward: { payment: {} },
+        tokenBudget: new BN(0),
+      })
+      .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+      .signers([sale.creator])
+      .rpc();
+    await openCampaign(sale, smallSale.totalTokens);
+
+    const referrer = await fundedKeypair();
+    await program.methods
+      .registerReferrer()
+      .accounts({ campaign: sale.campaign, referrer: referrer.publicKey })
+      .signers([referrer])
+      .rpc();
+    const [referrerStats] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("REFERRER"),
+        sale.campaign.toBuffer(),
+        referrer.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const paid = new BN(LAMPORTS_PER_SOL);
+    await donate(sale.campaign, await fundedKeypair(), paid, referrerStats);
+
+    const commission = paid.divn(20);
+    let stats = await program.account.referrerStats.fetch(referrerStats);
+    assert.ok(stats.referredPurchases.eqn(1));
+    assert.ok(stats.referredVolume.eq(paid));
+    assert.ok(stats.rewardsEarned.eq(commission));
+    let account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.referralPaymentOwed.eq(commission));
+
+    const claim = () =>
+      program.methods
+        .claimReferralRewards()
+        .accounts({
+          campaign: sale.campaign,
+          referrer: referrer.publicKey,
+          rewardVault: null,
+          rewardMint: null,
+          referrerTokenAccount: null,
+          tokenProgram: null,
+        })
+        .signers([referrer])
+        .rpc();
+    try {
+      await claim();
+      assert.fail("commissions wait for the sale to succeed");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "SaleNotSucceeded");
+    }
+
+    await program.methods
+      .finalize()
+      .accounts({ campaign: sale.campaign })
+      .rpc();
+    const before = await provider.connection.getBalance(referrer.publicKey);
+    await claim();
+    // The provider wallet pays the transaction fee
+    assert.equal(
+      (await provider.connection.getBalance(referrer.publicKey)) - before,
+      commission.toNumber()
+    );
+    stats = await program.account.referrerStats.fetch(referrerStats);
+    assert.ok(stats.rewardsClaimed.eq(commission));
+    account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.referralPaymentOwed.isZero());
+
+    try {
+      await claim();
+      assert.fail("a commission is only paid once");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "NothingToClaim");
+    }
+  });
+
+  it("Closes a cancelled sale that still credits a referrer", async () => {
+    const sale = await createCampaign(smallSale);
+    await program.methods
+      .configureReferrals({
+        commissionBps: 500,
+        reward: { payment: {} },
+        tokenBudget: new BN(0),
+      })
+      .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+      .signers([sale.creator])
+      .rpc();
+    await openCampaign(sale, smallSale.totalTokens);
+    const referrer = await fundedKeypair();
+    await program.methods
+      .registerReferrer()
+      .accounts({ campaign: sale.campaign, referrer: referrer.publicKey })
+      .signers([referrer])
+      .rpc();
+    const [referrerStats] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("REFERRER"),
+        sale.campaign.toBuffer(),
+        referrer.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const buyer = await fundedKeypair();
+    await donate(sale.campaign, buyer, new BN(200_000_000), referrerStats);
+
+    await program.methods
+      .cancelCampaign()
+      .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+      .signers([sale.creator])
+      .rpc();
+    await program.methods
+      .refund()
+      .accounts({ campaign: sale.campaign, buyer: buyer.publicKey })
+      .signers([buyer])
+      .rpc();
+    await program.methods
+      .closePosition()
+      .accounts({ campaign: sale.campaign, buyer: buyer.publicKey })
+      .rpc();
+    await program.methods
+      .reclaimUnsoldTokens()
+      .accountsPartial({
+        campaign: sale.campaign,
+        admin: sale.creator.publicKey,
+        tokenMint,
+        tokenVault: sale.tokenVault,
+        adminTokenAccount: getAssociatedTokenAddressSync(
+          tokenMint,
+          sale.creator.publicKey
+        ),
+      })
+      .signers([sale.creator])
+      .rpc();
+
+    // The commission is still on the books but will never be paid
+    const account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.referralPaymentOwed.gtn(0));
+    await program.methods
+      .closeCampaign()
+      .accounts({
+        campaign: sale.campaign,
+        admin: sale.creator.publicKey,
+        tokenVault: sale.tokenVault,
+        paymentVault: null,
+      })
+      .signers([sale.creator])
+      .rpc();
+    assert.isNull(
+      await program.account.campaign.fetchNullable(sale.campaign)
+    );
+  });
+
+  it("Pays bonus-token commissions and refuses self-referrals", async () => {
+    const sale = await createCampaign(smallSale);
+    const tokenBudget = new BN(1_000_000_000);
+    await program.methods
+      .configureReferrals({
+        commissionBps: 500, // 5% of the tokens bought
+        reward: { bonusTokens: {} },
+        tokenBudget,
+      })
+      .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+      .signers([sale.creator])
+      .rpc();
+    // The budget is deposited on top of the sale supply
+    await openCampaign(sale, smallSale.totalTokens.add(tokenBudget));
+
+    const referrer = await fundedKeypair();
+    await program.methods
+      .registerReferrer()
+      .accounts({ campaign: sale.campaign, referrer: referrer.publicKey })
+      .signers([referrer])
+      .rpc();
+    const [referrerStats] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("REFERRER"),
+        sale.campaign.toBuffer(),
+        referrer.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    try {
+      await donate(
+        sale.campaign,
+        referrer,
+        new BN(LAMPORTS_PER_SOL),
+        referrerStats
+      );
+      assert.fail("a referrer cannot refer their own purchase");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidReferrer");
+    }
+
+    await donate(
+      sale.campaign,
+      await fundedKeypair(),
+      new BN(LAMPORTS_PER_SOL),
+      referrerStats
+    );
+    const bonus = smallSale.totalTokens.divn(20);
+    let account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.referralTokensOwed.eq(bonus));
+    await program.methods
+      .finalize()
+      .accounts({ campaign: sale.campaign })
+      .rpc();
+
+    const referrerTokens = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      tokenMint,
+      referrer.publicKey
+    );
+    await program.methods
+      .claimReferralRewards()
+      .accounts({
+        campaign: sale.campaign,
+        referrer: referrer.publicKey,
+        rewardVault: sale.tokenVault,
+        rewardMint: tokenMint,
+        referrerTokenAccount: referrerTokens.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([referrer])
+      .rpc();
+    const received = await getAccount(
+      provider.connection,
+      referrerTokens.address
+    );
+    assert.equal(received.amount.toString(), bonus.toString());
+    const stats = await program.account.referrerStats.fetch(referrerStats);
+    assert.ok(stats.rewardsClaimed.eq(bonus));
+    account = await program.account.campaign.fetch(sale.campaign);
+    assert.ok(account.referralTokensOwed.isZero());
+  });
+
+  it("Refuses payment commissions on uniform-clearing auctions", async () => {
+    const sale = await createCampaign({
+      ...smallSale,
+      tokenPrice: new BN(200_000_000),
+      pricingMode: { dutchAuction: { floorPrice, uniformClearing: true } },
+    });
+    try {
+      await program.methods
+        .configureReferrals({
+          commissionBps: 500,
+          reward: { payment: {} },
+          tokenBudget: new BN(0),
+        })
+        .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+        .signers([sale.creator])
+        .rpc();
+      assert.fail("rebates would shrink the commission's base");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidReferralConfig");
+    }
+  });
+
+  it("Refuses a protocol fee recipient that cannot receive fees", async () => {
+    try {
+      await program.methods
+        .updateProtocolConfig(protocolFeeBps, PublicKey.default, admin)
+        .accounts({ admin })
+        .rpc();
+      assert.fail("the default pubkey should be rejected");
+    } catch (err) {
+      assert.equal(err.error.errorCode.code, "InvalidFeeRecipient");
+    }
+  });
+
+  it("Reports the tokens a wallet bought, 0 before any purchase", async () => {
+    const sale = await createCampaign(smallSale);
+    await openCampaign(sale, smallSale.totalTokens);
+    const buyer = await fundedKeypair();
+    const tokensBought = () =>
+      program.methods
+        .getTokensBought()
+        .accounts({ campaign: sale.campaign, user: buyer.publicKey })
+        .signers([buyer])
+        .view();
+
+    assert.ok((await tokensBought()).isZero());
+    await donate(sale.campaign, buyer, new BN(200_000_000));
+    assert.ok((await tokensBought()).eq(new BN(2e9)));
+  });
+
+  it("Blocks paused actions but never refunds", async () => {
+    const sale = await createCampaign(smallSale);
+    await openCampaign(sale, smallSale.totalTokens);
+    const everything = { donate: true, claims: true, withdrawals: true };
+    const asAdmin = {
+      campaign: sale.campaign,
+      authority: sale.creator.publicKey,
+    };
+    const pause = () =>
+      program.methods
+        .pause(everything, "incident review")
+        .accounts(asAdmin)
+        .signers([sale.creator])
+        .rpc();
+    const unpause = () =>
+      program.methods
+        .unpause()
+        .accounts(asAdmin)
+        .signers([sale.creator])
+        .rpc();
+    const expectPaused = async (action: Promise<string>) => {
+      try {
+        await action;
+        assert.fail("the action should be paused");
+      } catch (err) {
+        assert.equal(err.error.errorCode.code, "ActionPaused");
+      }
+    };
+
+    const buyer = await fundedKeypair();
+    await pause();
+    await expectPaused(donate(sale.campaign, buyer, new BN(LAMPORTS_PER_SOL)));
+    await unpause();
+    await donate(sale.campaign, buyer, new BN(LAMPORTS_PER_SOL));
+    await program.methods
+      .finalize()
+      .accounts({ campaign: sale.campaign })
+      .rpc();
+    await program.methods
+      .enableClaim()
+      .accounts({ campaign: sale.campaign, admin: sale.creator.publicKey })
+      .signers([sale.creator])
+      .rpc();
+
+    await pause();
+    await expectPaused(
+      program.methods
+        .claim()
+        .accounts({ campaign: sale.campaign, buyer: buyer.publicKey })
+        .signers([buyer])
+        .rpc()
+    );
+    await expectPaused(
+      program.methods
+        .withdraw(new BN(LAMPORTS_PER_SOL))
+        .accountsPartial({
+          campaign: sale.campaign,
+          user: sale.creator.publicKey,
+          treasury: sale.creator.publicKey,
+          feeRecipient,
+        })
+        .signers([sale.creator])
+        .rpc()
+    );
+
+    // A cancelled sale refunds its buyers whatever the pause says
+    const cancelled = await createCampaign(smallSale);
+    await openCampaign(cancelled, smallSale.totalTokens);
+    const refunded = await fundedKeypair();
+    await donate(cancelled.campaign, refunded, new BN(200_000_000));
+    await program.methods
+      .cancelCampaign()
+      .accounts({
+        campaign: cancelled.campaign,
+        admin: cancelled.creator.publicKey,
+      })
+      .signers([cancelled.creator])
+      .rpc();
+    await program.methods
+      .pause(everything, "incident review")
+      .accounts({
+        campaign: cancelled.campaign,
+        authority: cancelled.creator.publicKey,
+      })
+      .signers([cancelled.creator])
+      .rpc();
+    await program.methods
+      .refund()
+      .accounts({ campaign: cancelled.campaign, buyer: refunded.publicKey })
+      .signers([refunded])
+      .rpc();
+    const position = await program.account.buyerPosition.fetch(
+      positionPda(cancelled.campaign, refunded.publicKey)
+    );
+    assert.isTrue(position.refunded);
   });
 });